@@ -1,4 +1,14 @@
-use crate::shape::{BinaryOp, Shape, ShapeDef, ShapeOp, UnaryOp};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+  import::{Heightmap, SdfGrid},
+  material::Material,
+  nso::invert_affine,
+  profile::Profile,
+  shape::{Axis, BinaryOp, HexOrientation, Shape, ShapeDef, ShapeOp, UnaryOp},
+};
 
 // shape defs
 pub fn sphere(radius: f32) -> Shape {
@@ -7,9 +17,98 @@ pub fn sphere(radius: f32) -> Shape {
 pub fn box_(x: f32, y: f32, z: f32) -> Shape {
   Shape::ShapeDef(ShapeDef::RectPrismPrimitive { x, y, z })
 }
+pub fn cheap_box(x: f32, y: f32, z: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::CheapRectPrismPrimitive { x, y, z })
+}
+pub fn rounded_box(x: f32, y: f32, z: f32, radius: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::RoundedRectPrismPrimitive { x, y, z, radius })
+}
 pub fn cube(size: f32) -> Shape {
   Shape::ShapeDef(ShapeDef::CubePrimitive { size })
 }
+pub fn cylinder(radius: f32, height: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::CylinderPrimitive { radius, height })
+}
+pub fn cone(radius: f32, height: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::ConePrimitive { radius, height })
+}
+pub fn torus(major_radius: f32, minor_radius: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::TorusPrimitive {
+    major_radius,
+    minor_radius,
+  })
+}
+pub fn capsule(radius: f32, height: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::CapsulePrimitive { radius, height })
+}
+pub fn plane(x: f32, y: f32, z: f32, offset: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::PlanePrimitive {
+    normal: [x, y, z],
+    offset,
+  })
+}
+pub fn ellipsoid(x: f32, y: f32, z: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::EllipsoidPrimitive { x, y, z })
+}
+pub fn hex_prism(
+  radius: f32,
+  height: f32,
+  orientation: HexOrientation,
+) -> Shape {
+  Shape::ShapeDef(ShapeDef::HexPrismPrimitive {
+    radius,
+    height,
+    orientation,
+  })
+}
+pub fn extrude(profile: Profile, height: f32) -> Shape {
+  Shape::ShapeDef(ShapeDef::Extrude { profile, height })
+}
+pub fn revolve(profile: Profile, axis: Axis) -> Shape {
+  Shape::ShapeDef(ShapeDef::Revolve { profile, axis })
+}
+pub fn imported_mesh(grid: impl Into<Arc<SdfGrid>>) -> Shape {
+  Shape::ShapeDef(ShapeDef::ImportedMesh { grid: grid.into() })
+}
+pub fn heightfield(
+  width: f32,
+  depth: f32,
+  samples: impl Into<Arc<Heightmap>>,
+  scale: f32,
+) -> Shape {
+  Shape::ShapeDef(ShapeDef::Heightfield {
+    width,
+    depth,
+    samples: samples.into(),
+    scale,
+  })
+}
+
+// profiles
+pub fn circle(radius: f32) -> Profile {
+  Profile::Circle { radius }
+}
+pub fn rect(width: f32, height: f32) -> Profile {
+  Profile::Rect { width, height }
+}
+pub fn polygon(points: Vec<[f32; 2]>) -> Profile {
+  Profile::Polygon { points }
+}
+pub fn translate_profile(profile: Profile, u: f32, v: f32) -> Profile {
+  Profile::Translate {
+    offset:  [u, v],
+    profile: Box::new(profile),
+  }
+}
+pub fn profile_union(a: Profile, b: Profile) -> Profile {
+  Profile::Union(Box::new(a), Box::new(b))
+}
+pub fn profile_difference(a: Profile, b: Profile) -> Profile {
+  Profile::Difference(Box::new(a), Box::new(b))
+}
+pub fn profile_intersection(a: Profile, b: Profile) -> Profile {
+  Profile::Intersection(Box::new(a), Box::new(b))
+}
 
 // unary ops
 pub fn translate(shape: Shape, x: f32, y: f32, z: f32) -> Shape {
@@ -24,10 +123,113 @@ pub fn scale(shape: Shape, x: f32, y: f32, z: f32) -> Shape {
     Box::new(shape),
   ))
 }
-pub fn matrix_transform(shape: Shape, matrix: [f32; 16]) -> Shape {
-  Shape::ShapeOp(ShapeOp::UnaryOp(
+/// Applies a column-major affine matrix to a shape, failing if the matrix is
+/// singular. See `UnaryOp::MatrixTransform`.
+pub fn matrix_transform(shape: Shape, matrix: [f32; 16]) -> Result<Shape> {
+  if invert_affine(matrix).is_none() {
+    return Err(anyhow!("matrix transform {:?} is singular", matrix));
+  }
+  Ok(Shape::ShapeOp(ShapeOp::UnaryOp(
     UnaryOp::MatrixTransform { matrix },
     Box::new(shape),
+  )))
+}
+pub fn rotate_euler(shape: Shape, x: f32, y: f32, z: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::RotateEuler { angles: [x, y, z] },
+    Box::new(shape),
+  ))
+}
+pub fn rotate_axis(shape: Shape, x: f32, y: f32, z: f32, angle: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::RotateAxis {
+      axis: [x, y, z],
+      angle,
+    },
+    Box::new(shape),
+  ))
+}
+pub fn rotate_quat(shape: Shape, x: f32, y: f32, z: f32, w: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::RotateQuat { quat: [x, y, z, w] },
+    Box::new(shape),
+  ))
+}
+pub fn rotate_x(shape: Shape, angle: f32) -> Shape {
+  rotate_axis(shape, 1.0, 0.0, 0.0, angle)
+}
+pub fn rotate_y(shape: Shape, angle: f32) -> Shape {
+  rotate_axis(shape, 0.0, 1.0, 0.0, angle)
+}
+pub fn rotate_z(shape: Shape, angle: f32) -> Shape {
+  rotate_axis(shape, 0.0, 0.0, 1.0, angle)
+}
+pub fn repeat(shape: Shape, x: f32, y: f32, z: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Repeat { period: [x, y, z] },
+    Box::new(shape),
+  ))
+}
+pub fn repeat_finite(shape: Shape, period: [f32; 3], count: [u32; 3]) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::RepeatFinite { period, count },
+    Box::new(shape),
+  ))
+}
+pub fn mirror(shape: Shape, axes: [bool; 3], offset: [f32; 3]) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Mirror { axes, offset },
+    Box::new(shape),
+  ))
+}
+pub fn radial_symmetry(shape: Shape, axis: Axis, count: u32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::RadialSymmetry { axis, count },
+    Box::new(shape),
+  ))
+}
+pub fn twist(shape: Shape, rate: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(UnaryOp::Twist { rate }, Box::new(shape)))
+}
+pub fn bend(shape: Shape, rate: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(UnaryOp::Bend { rate }, Box::new(shape)))
+}
+pub fn taper(shape: Shape, rate: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(UnaryOp::Taper { rate }, Box::new(shape)))
+}
+pub fn offset(shape: Shape, distance: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Offset { distance },
+    Box::new(shape),
+  ))
+}
+pub fn shell(shape: Shape, thickness: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Shell { thickness },
+    Box::new(shape),
+  ))
+}
+pub fn elongate(shape: Shape, x: f32, y: f32, z: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Elongate { h: [x, y, z] },
+    Box::new(shape),
+  ))
+}
+pub fn displace(
+  shape: Shape,
+  seed: u32,
+  frequency: f32,
+  amplitude: f32,
+  octaves: u32,
+) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Displace {
+      seed,
+      frequency,
+      amplitude,
+      octaves,
+    },
+    Box::new(shape),
   ))
 }
 pub fn recolor(shape: Shape, r: u8, g: u8, b: u8) -> Shape {
@@ -36,6 +238,101 @@ pub fn recolor(shape: Shape, r: u8, g: u8, b: u8) -> Shape {
     Box::new(shape),
   ))
 }
+pub fn linear_gradient(
+  shape: Shape,
+  from: [u8; 3],
+  to: [u8; 3],
+  axis: Axis,
+  start: f32,
+  end: f32,
+) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::LinearGradient {
+      colors: [from, to],
+      axis,
+      start,
+      end,
+    },
+    Box::new(shape),
+  ))
+}
+pub fn radial_gradient(
+  shape: Shape,
+  from: [u8; 3],
+  to: [u8; 3],
+  radius: f32,
+) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::RadialGradient {
+      colors: [from, to],
+      radius,
+    },
+    Box::new(shape),
+  ))
+}
+pub fn stripes(
+  shape: Shape,
+  a: [u8; 3],
+  b: [u8; 3],
+  axis: Axis,
+  width: f32,
+) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Stripes {
+      colors: [a, b],
+      axis,
+      width,
+    },
+    Box::new(shape),
+  ))
+}
+pub fn checker(shape: Shape, a: [u8; 3], b: [u8; 3], size: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Checker {
+      colors: [a, b],
+      size,
+    },
+    Box::new(shape),
+  ))
+}
+pub fn noise_tint(
+  shape: Shape,
+  rgb: [u8; 3],
+  strength: f32,
+  seed: u32,
+  frequency: f32,
+  octaves: u32,
+) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::NoiseTint {
+      rgb,
+      strength,
+      seed,
+      frequency,
+      octaves,
+    },
+    Box::new(shape),
+  ))
+}
+pub fn material(
+  shape: Shape,
+  id: u32,
+  roughness: f32,
+  metallic: f32,
+  emissive: [f32; 3],
+) -> Shape {
+  Shape::ShapeOp(ShapeOp::UnaryOp(
+    UnaryOp::Material {
+      id,
+      material: Material {
+        roughness,
+        metallic,
+        emissive,
+      },
+    },
+    Box::new(shape),
+  ))
+}
 pub fn abbreviate(shape: Shape, threshold: f32) -> Shape {
   Shape::ShapeOp(ShapeOp::UnaryOp(
     UnaryOp::Abbreviate { threshold },
@@ -68,3 +365,24 @@ pub fn replacement(a: Shape, b: Shape) -> Shape {
     Box::new(b),
   ))
 }
+pub fn smooth_union(a: Shape, b: Shape, k: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::BinaryOp(
+    BinaryOp::SmoothUnion { k },
+    Box::new(a),
+    Box::new(b),
+  ))
+}
+pub fn smooth_difference(a: Shape, b: Shape, k: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::BinaryOp(
+    BinaryOp::SmoothDifference { k },
+    Box::new(a),
+    Box::new(b),
+  ))
+}
+pub fn smooth_intersection(a: Shape, b: Shape, k: f32) -> Shape {
+  Shape::ShapeOp(ShapeOp::BinaryOp(
+    BinaryOp::SmoothIntersection { k },
+    Box::new(a),
+    Box::new(b),
+  ))
+}
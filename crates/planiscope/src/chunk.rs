@@ -0,0 +1,790 @@
+//! Meshing large regions as a grid of chunks.
+//!
+//! Every chunk is meshed on its own, so chunks can be meshed in parallel and
+//! remeshed one at a time, each at its own depth. Rather than the octree
+//! mesher, chunks are meshed with surface nets over a lattice shared by the
+//! whole grid, so that their seams match exactly:
+//!
+//! - Each cell gets one vertex, at the mean of the points where the surface
+//!   crosses the lattice around the cell's faces, sampled as finely as the
+//!   smallest cells touching it.
+//! - Each lattice edge the surface crosses gives a polygon joining the cells
+//!   around it. Where cells of different sizes meet, only the edges of the
+//!   smallest cells are used, and a larger cell fills two corners of their
+//!   polygons, giving triangles instead of quads. This is the same across the
+//!   faces, edges and corners of chunks.
+//! - Each polygon belongs to the chunk containing the start of its edge, which
+//!   is decided on the integer lattice, so a polygon on a seam is kept by
+//!   exactly one chunk.
+//!
+//! Chunks compute the vertices they share with their neighbours from the same
+//! samples in the same order, so neighbouring meshes meet vertex for vertex.
+
+use std::{
+  collections::HashMap,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use fidget::eval::{Family, Tape};
+
+use crate::{
+  comp::{CompilationSettings, Composition, CompositionTapes},
+  mesh::{
+    eval_colors, eval_material_ids, eval_normals, CancelToken, Cancelled,
+    ColorMode, FullMesh, MeshSettings, NormalsMode, EVAL_CHUNK_SIZE,
+  },
+};
+
+/// The deepest a chunk can be meshed at. The lattice is addressed with `i32`s
+/// in half units of the finest cells, so this leaves room for grids reaching
+/// thousands of chunks from their origin.
+pub const MAX_CHUNK_DEPTH: u8 = 16;
+
+/// The coordinates of a chunk in a `ChunkGrid`.
+pub type ChunkCoord = [i32; 3];
+
+/// A grid of cubic chunks covering a region.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkGrid {
+  /// The minimum corner of the chunk at `[0, 0, 0]`.
+  pub origin:     glam::Vec3A,
+  /// The length of each side of a chunk.
+  pub chunk_size: f32,
+  /// The first chunk of the region on each axis.
+  pub min:        ChunkCoord,
+  /// The chunk just past the end of the region on each axis.
+  pub max:        ChunkCoord,
+}
+
+impl ChunkGrid {
+  /// The coordinates of every chunk in the region.
+  pub fn chunks(&self) -> impl Iterator<Item = ChunkCoord> + '_ {
+    let [min_x, min_y, min_z] = self.min;
+    let [max_x, max_y, max_z] = self.max;
+    (min_x..max_x).flat_map(move |x| {
+      (min_y..max_y).flat_map(move |y| (min_z..max_z).map(move |z| [x, y, z]))
+    })
+  }
+
+  /// The minimum corner of a chunk.
+  pub fn chunk_min(&self, chunk: ChunkCoord) -> glam::Vec3A {
+    self.origin + glam::IVec3::from_array(chunk).as_vec3a() * self.chunk_size
+  }
+
+  pub fn chunk_center(&self, chunk: ChunkCoord) -> glam::Vec3A {
+    self.chunk_min(chunk) + self.chunk_size / 2.0
+  }
+
+  /// The coordinates of the chunk containing a point.
+  pub fn chunk_at(&self, point: glam::Vec3A) -> ChunkCoord {
+    ((point - self.origin) / self.chunk_size)
+      .floor()
+      .as_ivec3()
+      .to_array()
+  }
+}
+
+/// Meshes every chunk of a grid, in parallel.
+///
+/// `depth` gives the depth of each chunk, so a chunk at depth `d` is `2^d`
+/// cells across, up to `MAX_CHUNK_DEPTH`. Of `settings`, only the threads,
+/// which are shared between chunks, and the normals and colors apply.
+/// `progress` is called with the number of chunks meshed so far and the number
+/// of chunks in total.
+///
+/// The meshes are in the composition's coordinates, and together they're
+/// watertight wherever the surface doesn't leave the grid. Chunks without any
+/// surface are left out of the map.
+pub fn mesh_chunks<T: Family>(
+  composition: &Composition,
+  grid: &ChunkGrid,
+  settings: &MeshSettings,
+  depth: impl Fn(ChunkCoord) -> u8,
+  cancel: &CancelToken,
+  progress: impl Fn(usize, usize) + Sync,
+) -> Result<HashMap<ChunkCoord, FullMesh>>
+where
+  Tape<T>: Send + Sync,
+{
+  let depths: HashMap<ChunkCoord, u8> =
+    grid.chunks().map(|chunk| (chunk, depth(chunk))).collect();
+  if depths.is_empty() {
+    return Ok(HashMap::new());
+  }
+  let lattice = Lattice::new(*grid, depths)?;
+
+  let comp_settings = CompilationSettings {
+    min_voxel_size: lattice.unit(),
+  };
+  // the lattice is in the composition's coordinates, so nothing to normalize
+  let tapes = composition.compile_tapes::<T>(
+    &comp_settings,
+    settings.colors,
+    |node, _| node,
+  )?;
+
+  let chunks = grid.chunks().collect::<Vec<_>>();
+  let next_chunk = AtomicUsize::new(0);
+  let meshed = AtomicUsize::new(0);
+  let workers = (settings.threads as usize).clamp(1, chunks.len());
+  let meshes = std::thread::scope(|scope| {
+    let handles = (0..workers)
+      .map(|_| {
+        scope.spawn(|| {
+          let mut meshes = Vec::new();
+          while let Some(chunk) =
+            chunks.get(next_chunk.fetch_add(1, Ordering::Relaxed))
+          {
+            let mesh = mesh_chunk(&lattice, &tapes, *chunk, settings, cancel)?;
+            progress(meshed.fetch_add(1, Ordering::Relaxed) + 1, chunks.len());
+            if !mesh.triangles.is_empty() {
+              meshes.push((*chunk, mesh));
+            }
+          }
+          Ok::<_, Cancelled>(meshes)
+        })
+      })
+      .collect::<Vec<_>>();
+    handles
+      .into_iter()
+      .map(|handle| handle.join().expect("chunk meshing panicked"))
+      .collect::<Result<Vec<_>, Cancelled>>()
+  })?;
+
+  Ok(meshes.into_iter().flatten().collect())
+}
+
+/// Meshes the polygons belonging to one chunk.
+fn mesh_chunk<T: Family>(
+  lattice: &Lattice,
+  tapes: &CompositionTapes<T>,
+  chunk: ChunkCoord,
+  settings: &MeshSettings,
+  cancel: &CancelToken,
+) -> Result<FullMesh, Cancelled> {
+  let mut samples = Samples::new(lattice, &tapes.solid);
+
+  let edges = lattice
+    .chunk_edges(chunk)
+    .into_iter()
+    .filter_map(|(start, axis)| {
+      let (cells, length) = lattice.edge_cells(start, axis)?;
+      let end = start + axis_step(axis, length);
+      Some((start, end, cells))
+    })
+    .collect::<Vec<_>>();
+  samples.sample(
+    edges.iter().flat_map(|(start, end, _)| [*start, *end]),
+    cancel,
+  )?;
+
+  // each edge the surface crosses gives a polygon, wound so that it faces out
+  // of the solid
+  let polygons = edges
+    .into_iter()
+    .filter_map(|(start, end, mut cells)| {
+      let inside = samples.get(start) < 0.0;
+      if inside == (samples.get(end) < 0.0) {
+        return None;
+      }
+      if !inside {
+        cells.reverse();
+      }
+      Some(cells)
+    })
+    .collect::<Vec<_>>();
+
+  let mut cells = polygons.iter().flatten().copied().collect::<Vec<_>>();
+  cells.sort_unstable();
+  cells.dedup();
+  let resolutions = cells
+    .iter()
+    .map(|cell| lattice.cell_resolution(*cell))
+    .collect::<Vec<_>>();
+  samples.sample(
+    cells
+      .iter()
+      .zip(&resolutions)
+      .flat_map(|(cell, resolution)| {
+        cell_surface(*cell, *resolution).flat_map(move |(start, axis)| {
+          [start, start + axis_step(axis, *resolution)]
+        })
+      }),
+    cancel,
+  )?;
+
+  let vertices = cells
+    .iter()
+    .zip(&resolutions)
+    .map(|(cell, resolution)| samples.cell_vertex(*cell, *resolution))
+    .collect::<Vec<_>>();
+  let vertex_indices = cells
+    .iter()
+    .enumerate()
+    .map(|(i, cell)| (*cell, i as u32))
+    .collect::<HashMap<_, _>>();
+  let triangles = polygons
+    .iter()
+    .flat_map(|cells| {
+      let indices = cells
+        .iter()
+        .map(|cell| vertex_indices[cell])
+        .collect::<Vec<_>>();
+      (1..indices.len() - 1)
+        .map(move |i| glam::UVec3::new(indices[0], indices[i], indices[i + 1]))
+    })
+    .collect();
+
+  if cancel.is_cancelled() {
+    return Err(Cancelled);
+  }
+  let axes = [0, 1, 2].map(|axis| {
+    vertices
+      .iter()
+      .map(|v: &glam::Vec3A| v[axis])
+      .collect::<Vec<_>>()
+  });
+  let [xs, ys, zs] = &axes;
+  Ok(FullMesh {
+    normals: match settings.normals {
+      NormalsMode::Smooth => Some(eval_normals(&tapes.solid, xs, ys, zs)),
+      NormalsMode::Flat => None,
+    },
+    colors: match (settings.colors, &tapes.colors) {
+      (ColorMode::Vertex, Some(colors)) => {
+        Some(eval_colors(colors, xs, ys, zs))
+      }
+      _ => None,
+    },
+    material_ids: Some(eval_material_ids(&tapes.material, xs, ys, zs)),
+    vertices,
+    triangles,
+  })
+}
+
+/// One step of `length` along an axis.
+fn axis_step(axis: usize, length: i32) -> glam::IVec3 {
+  let mut step = glam::IVec3::ZERO;
+  step[axis] = length;
+  step
+}
+
+/// A cell of the lattice, as its minimum corner and the length of its sides,
+/// in lattice units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Cell {
+  min:  [i32; 3],
+  size: i32,
+}
+
+/// The lattice the chunks of a grid are meshed on. Its units are the size of
+/// the cells of the deepest chunk, and its origin is the grid's.
+struct Lattice {
+  grid:         ChunkGrid,
+  depths:       HashMap<ChunkCoord, u8>,
+  finest_depth: u8,
+  chunk_units:  i32,
+}
+
+impl Lattice {
+  /// Sets up the lattice for a grid with the given depth for each chunk.
+  /// Fails if a depth is over `MAX_CHUNK_DEPTH`, or if the grid reaches too
+  /// far from its origin to address at the finest depth.
+  fn new(grid: ChunkGrid, depths: HashMap<ChunkCoord, u8>) -> Result<Self> {
+    let finest_depth = depths.values().max().copied().unwrap_or(0);
+    if finest_depth > MAX_CHUNK_DEPTH {
+      return Err(anyhow!(
+        "chunk depth {} is over the limit of {}",
+        finest_depth,
+        MAX_CHUNK_DEPTH
+      ));
+    }
+    let chunk_units = 1_i32
+      .checked_shl(finest_depth.into())
+      .ok_or_else(|| anyhow!("chunk depth {} is too deep", finest_depth))?;
+
+    // neighbouring chunks are looked up one past the grid, in half units
+    let reach = grid
+      .min
+      .iter()
+      .chain(&grid.max)
+      .map(|c| c.unsigned_abs() as u64 + 1)
+      .max()
+      .unwrap_or(1);
+    if reach * 2 * chunk_units as u64 > i32::MAX as u64 {
+      return Err(anyhow!(
+        "chunk grid reaches too far from its origin to mesh at depth {}",
+        finest_depth
+      ));
+    }
+
+    Ok(Lattice {
+      grid,
+      depths,
+      finest_depth,
+      chunk_units,
+    })
+  }
+
+  /// The number of lattice units across a chunk.
+  fn chunk_units(&self) -> i32 {
+    self.chunk_units
+  }
+
+  /// The length of a lattice unit.
+  fn unit(&self) -> f32 {
+    self.grid.chunk_size / self.chunk_units() as f32
+  }
+
+  /// The position of a point given in lattice units.
+  fn position(&self, point: glam::Vec3A) -> glam::Vec3A {
+    self.grid.origin + point * self.unit()
+  }
+
+  /// The size of the cells of a chunk, or `None` if it isn't in the grid.
+  fn cell_size(&self, chunk: ChunkCoord) -> Option<i32> {
+    let depth = self.depths.get(&chunk)?;
+    let shift = self.finest_depth.checked_sub(*depth)?;
+    1_i32.checked_shl(shift.into())
+  }
+
+  /// The cell containing a point given in half lattice units, or `None` if
+  /// it's outside the grid. The point mustn't be on the boundary of a cell.
+  fn cell_at(&self, half_point: glam::IVec3) -> Option<Cell> {
+    let chunk =
+      half_point.div_euclid(glam::IVec3::splat(2 * self.chunk_units()));
+    let size = self.cell_size(chunk.to_array())?;
+    let min = half_point.div_euclid(glam::IVec3::splat(2 * size)) * size;
+    Some(Cell {
+      min: min.to_array(),
+      size,
+    })
+  }
+
+  /// The size of the smallest cells among the chunks around `chunk` that are
+  /// offset only along the axes where `touching` allows, including itself.
+  fn smallest_cells(
+    &self,
+    chunk: ChunkCoord,
+    touching: impl Fn(usize, i32) -> bool,
+  ) -> i32 {
+    let mut smallest = i32::MAX;
+    for x in -1..=1 {
+      for y in -1..=1 {
+        for z in -1..=1 {
+          let offset = [x, y, z];
+          if (0..3)
+            .any(|axis| offset[axis] != 0 && !touching(axis, offset[axis]))
+          {
+            continue;
+          }
+          let neighbour = [chunk[0] + x, chunk[1] + y, chunk[2] + z];
+          if let Some(size) = self.cell_size(neighbour) {
+            smallest = smallest.min(size);
+          }
+        }
+      }
+    }
+    smallest
+  }
+
+  /// The spacing a cell's vertex is sampled at: the size of the smallest
+  /// cells touching it, so that it takes in every edge around it.
+  fn cell_resolution(&self, cell: Cell) -> i32 {
+    let units = self.chunk_units();
+    let chunk = cell.min.map(|min| min.div_euclid(units));
+    self.smallest_cells(chunk, |axis, direction| {
+      let chunk_min = chunk[axis] * units;
+      if direction < 0 {
+        cell.min[axis] == chunk_min
+      } else {
+        cell.min[axis] + cell.size == chunk_min + units
+      }
+    })
+  }
+
+  /// The edges that may give polygons belonging to a chunk, as their starts
+  /// and axes. Every edge of the chunk's own cells starting inside it is
+  /// included, as are the edges of any smaller cells across its minimum
+  /// faces.
+  fn chunk_edges(&self, chunk: ChunkCoord) -> Vec<(glam::IVec3, usize)> {
+    let Some(size) = self.cell_size(chunk) else {
+      return Vec::new();
+    };
+    let units = self.chunk_units();
+    let chunk_min = glam::IVec3::from_array(chunk) * units;
+    let mut edges = Vec::new();
+    // adds the edges leaving each point of a lattice of `spacing`, keeping to
+    // the chunk's minimum face across `flat_axis` if there is one
+    let mut add_lattice = |spacing: i32, flat_axis: Option<usize>| {
+      let steps = [0, 1, 2].map(|axis| {
+        if flat_axis == Some(axis) {
+          1
+        } else {
+          units / spacing
+        }
+      });
+      for i in 0..steps[0] {
+        for j in 0..steps[1] {
+          for k in 0..steps[2] {
+            let start = chunk_min + glam::IVec3::new(i, j, k) * spacing;
+            edges.extend((0..3).map(|axis| (start, axis)));
+          }
+        }
+      }
+    };
+
+    add_lattice(size, None);
+    let smallest = self.smallest_cells(chunk, |_, direction| direction < 0);
+    if smallest < size {
+      for axis in 0..3 {
+        add_lattice(smallest, Some(axis));
+      }
+    }
+    edges.sort_unstable_by_key(|(start, axis)| (start.to_array(), *axis));
+    edges.dedup();
+    edges
+  }
+
+  /// The cells around the edge leaving `start` along `axis`, counterclockwise
+  /// about the axis, and the length of the edge. Returns `None` unless the
+  /// edge is an edge of the smallest cells around it, with cells on every
+  /// side. A larger cell on more than one side appears once.
+  fn edge_cells(
+    &self,
+    start: glam::IVec3,
+    axis: usize,
+  ) -> Option<(Vec<Cell>, i32)> {
+    let [u, v] = [(axis + 1) % 3, (axis + 2) % 3];
+    let mut cells: Vec<Cell> = Vec::with_capacity(4);
+    for [du, dv] in [[-1, -1], [1, -1], [1, 1], [-1, 1]] {
+      let mut half_point = start * 2;
+      half_point[axis] += 1;
+      half_point[u] += du;
+      half_point[v] += dv;
+      let cell = self.cell_at(half_point)?;
+      if cells.last() != Some(&cell) {
+        cells.push(cell);
+      }
+    }
+    if cells.len() > 1 && cells.first() == cells.last() {
+      cells.pop();
+    }
+    if cells.len() < 3 {
+      return None;
+    }
+
+    let length = cells.iter().map(|cell| cell.size).min()?;
+    if start[axis].rem_euclid(length) != 0 {
+      return None;
+    }
+    Some((cells, length))
+  }
+}
+
+/// The segments of `spacing` along the lattice over the surface of a cell, as
+/// their starts and axes, in a fixed order.
+fn cell_surface(
+  cell: Cell,
+  spacing: i32,
+) -> impl Iterator<Item = (glam::IVec3, usize)> {
+  let steps = cell.size / spacing;
+  let min = glam::IVec3::from_array(cell.min);
+  (0..3).flat_map(move |axis| {
+    let [u, v] = [(axis + 1) % 3, (axis + 2) % 3];
+    (0..steps).flat_map(move |i| {
+      (0..=steps).flat_map(move |j| {
+        (0..=steps)
+          .filter(move |k| j == 0 || j == steps || *k == 0 || *k == steps)
+          .map(move |k| {
+            let mut step = glam::IVec3::ZERO;
+            step[axis] = i;
+            step[u] = j;
+            step[v] = k;
+            (min + step * spacing, axis)
+          })
+      })
+    })
+  })
+}
+
+/// Samples of the solid at lattice points.
+struct Samples<'a, T: Family> {
+  lattice: &'a Lattice,
+  tape:    &'a Tape<T>,
+  values:  HashMap<[i32; 3], f32>,
+}
+
+impl<'a, T: Family> Samples<'a, T> {
+  fn new(lattice: &'a Lattice, tape: &'a Tape<T>) -> Self {
+    Samples {
+      lattice,
+      tape,
+      values: HashMap::new(),
+    }
+  }
+
+  /// Samples any of `points` that haven't been yet, checking for
+  /// cancellation between chunks of them.
+  fn sample(
+    &mut self,
+    points: impl Iterator<Item = glam::IVec3>,
+    cancel: &CancelToken,
+  ) -> Result<(), Cancelled> {
+    let mut points = points
+      .map(|point| point.to_array())
+      .filter(|point| !self.values.contains_key(point))
+      .collect::<Vec<_>>();
+    points.sort_unstable();
+    points.dedup();
+
+    let evaluator = self.tape.new_float_slice_evaluator();
+    for points in points.chunks(EVAL_CHUNK_SIZE) {
+      if cancel.is_cancelled() {
+        return Err(Cancelled);
+      }
+      let positions = points
+        .iter()
+        .map(|point| {
+          let point = glam::IVec3::from_array(*point).as_vec3a();
+          self.lattice.position(point)
+        })
+        .collect::<Vec<_>>();
+      let [xs, ys, zs] = [0, 1, 2]
+        .map(|axis| positions.iter().map(|p| p[axis]).collect::<Vec<_>>());
+      let values = match evaluator.eval(&xs, &ys, &zs, &[]) {
+        Err(_) => panic!("solid evaluation failed"),
+        Ok(values) => values,
+      };
+      self.values.extend(points.iter().copied().zip(values));
+    }
+    Ok(())
+  }
+
+  fn get(&self, point: glam::IVec3) -> f32 {
+    self.values[&point.to_array()]
+  }
+
+  /// The vertex of a cell: the mean of the points where the surface crosses
+  /// the segments of its surface at `resolution`.
+  fn cell_vertex(&self, cell: Cell, resolution: i32) -> glam::Vec3A {
+    let mut sum = glam::Vec3A::ZERO;
+    let mut crossings = 0;
+    for (start, axis) in cell_surface(cell, resolution) {
+      let a = self.get(start);
+      let b = self.get(start + axis_step(axis, resolution));
+      if (a < 0.0) == (b < 0.0) {
+        continue;
+      }
+      let mut crossing = start.as_vec3a();
+      crossing[axis] += a / (a - b) * resolution as f32;
+      sum += crossing;
+      crossings += 1;
+    }
+    // every cell of a polygon has a crossing on its surface, but keep to the
+    // middle of the cell if it somehow doesn't
+    let point = if crossings == 0 {
+      glam::IVec3::from_array(cell.min).as_vec3a() + cell.size as f32 / 2.0
+    } else {
+      sum / crossings as f32
+    };
+    self.lattice.position(point)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn grid() -> ChunkGrid {
+    ChunkGrid {
+      origin:     glam::Vec3A::splat(-4.0),
+      chunk_size: 4.0,
+      min:        [0, 0, 0],
+      max:        [2, 1, 2],
+    }
+  }
+
+  #[test]
+  fn test_chunk_grid() {
+    let grid = grid();
+    assert_eq!(grid.chunks().count(), 4);
+    assert_eq!(grid.chunk_min([1, 0, 1]), glam::Vec3A::new(0.0, -4.0, 0.0));
+    assert_eq!(grid.chunk_center([0, 0, 0]), glam::Vec3A::splat(-2.0));
+    assert_eq!(grid.chunk_at(glam::Vec3A::new(0.0, -4.0, -0.1)), [1, 0, 0]);
+  }
+
+  #[test]
+  fn test_edge_cells() {
+    let grid = ChunkGrid {
+      origin:     glam::Vec3A::ZERO,
+      chunk_size: 4.0,
+      min:        [0, 0, 0],
+      max:        [2, 1, 1],
+    };
+    let depths = HashMap::from([([0, 0, 0], 1), ([1, 0, 0], 2)]);
+    let lattice = Lattice::new(grid, depths).unwrap();
+    let coarse = Cell {
+      min:  [2, 0, 0],
+      size: 2,
+    };
+
+    // an edge of the smaller cells on the seam is shared with a larger cell,
+    // giving a triangle
+    let (cells, length) =
+      lattice.edge_cells(glam::IVec3::new(4, 1, 1), 1).unwrap();
+    assert_eq!(length, 1);
+    assert_eq!(cells.len(), 3);
+    assert_eq!(cells[0], coarse);
+    // the larger cell's own edges along the seam are split into those of the
+    // smaller cells, and a line across the middle of its face isn't an edge
+    let (cells, length) =
+      lattice.edge_cells(glam::IVec3::new(4, 2, 0), 2).unwrap();
+    assert_eq!(length, 1);
+    assert_eq!(cells.len(), 4);
+    assert!(lattice.edge_cells(glam::IVec3::new(3, 1, 0), 2).is_none());
+    // edges on the outside of the grid have no cells beyond them
+    assert!(lattice.edge_cells(glam::IVec3::new(4, 0, 1), 0).is_none());
+
+    // the larger cell's vertex is sampled as finely as the smaller cells
+    assert_eq!(lattice.cell_resolution(coarse), 1);
+    let inner = Cell {
+      min:  [0, 0, 0],
+      size: 2,
+    };
+    assert_eq!(lattice.cell_resolution(inner), 2);
+  }
+
+  fn mesh_sphere(
+    grid: &ChunkGrid,
+    radius: f32,
+    depth: impl Fn(ChunkCoord) -> u8,
+  ) -> HashMap<ChunkCoord, FullMesh> {
+    let composition =
+      Composition::from(vec![(crate::builder::sphere(radius), [0.0; 3])]);
+    mesh_chunks::<fidget::vm::Eval>(
+      &composition,
+      grid,
+      &MeshSettings::default().threads(2),
+      depth,
+      &CancelToken::new(),
+      |_, _| {},
+    )
+    .unwrap()
+  }
+
+  /// Checks that every edge of the meshes, matched by the positions of its
+  /// ends, is shared by exactly two triangles.
+  fn assert_watertight<'a>(meshes: impl Iterator<Item = &'a FullMesh>) {
+    let mut edges: HashMap<[[u32; 3]; 2], usize> = HashMap::new();
+    for mesh in meshes {
+      for triangle in &mesh.triangles {
+        let corners = triangle
+          .to_array()
+          .map(|i| mesh.vertices[i as usize].to_array().map(f32::to_bits));
+        for i in 0..3 {
+          let mut edge = [corners[i], corners[(i + 1) % 3]];
+          edge.sort();
+          *edges.entry(edge).or_default() += 1;
+        }
+      }
+    }
+    assert!(!edges.is_empty());
+    assert!(edges.values().all(|count| *count == 2));
+  }
+
+  #[test]
+  fn test_chunk_seams() {
+    // a sphere across two chunks at the same depth
+    let grid = ChunkGrid {
+      origin:     glam::Vec3A::new(-4.0, -2.0, -2.0),
+      chunk_size: 4.0,
+      min:        [0, 0, 0],
+      max:        [2, 1, 1],
+    };
+    let meshes = mesh_sphere(&grid, 1.4, |_| 4);
+    assert_eq!(meshes.len(), 2);
+    assert_watertight(meshes.values());
+
+    // a sphere across the shared corner of eight chunks, with neighbours
+    // across faces, edges and the corner at different depths
+    let grid = ChunkGrid {
+      origin:     glam::Vec3A::splat(-4.0),
+      chunk_size: 4.0,
+      min:        [0, 0, 0],
+      max:        [2, 2, 2],
+    };
+    let meshes = mesh_sphere(&grid, 2.6, |[x, y, z]| {
+      2 + (x + 2 * y + 3 * z).rem_euclid(3) as u8
+    });
+    assert_eq!(meshes.len(), 8);
+    assert_watertight(meshes.values());
+  }
+
+  #[test]
+  fn test_depth_limits() {
+    let grid = ChunkGrid {
+      origin:     glam::Vec3A::ZERO,
+      chunk_size: 4.0,
+      min:        [0, 0, 0],
+      max:        [1, 1, 1],
+    };
+    let depths = |depth| HashMap::from([([0, 0, 0], depth)]);
+    assert!(Lattice::new(grid, depths(MAX_CHUNK_DEPTH)).is_ok());
+    assert!(Lattice::new(grid, depths(MAX_CHUNK_DEPTH + 1)).is_err());
+    assert!(Lattice::new(grid, depths(u8::MAX)).is_err());
+    let far = ChunkGrid {
+      max: [1 << 20, 1, 1],
+      ..grid
+    };
+    assert!(Lattice::new(far, depths(MAX_CHUNK_DEPTH)).is_err());
+
+    let composition =
+      Composition::from(vec![(crate::builder::sphere(1.0), [0.0; 3])]);
+    let meshes = mesh_chunks::<fidget::vm::Eval>(
+      &composition,
+      &grid,
+      &MeshSettings::default(),
+      |_| MAX_CHUNK_DEPTH + 1,
+      &CancelToken::new(),
+      |_, _| {},
+    );
+    assert!(meshes.is_err());
+  }
+
+  #[test]
+  fn test_seam_ties() {
+    // the edges on the seams lie exactly on the faces between chunks, and each
+    // polygon around them is kept by exactly one chunk, so the chunks give
+    // the same triangles as one chunk covering them all
+    let triangles = |meshes: HashMap<ChunkCoord, FullMesh>| {
+      let mut triangles = meshes
+        .values()
+        .flat_map(|mesh| {
+          mesh.triangles.iter().map(|triangle| {
+            triangle
+              .to_array()
+              .map(|i| mesh.vertices[i as usize].to_array().map(f32::to_bits))
+          })
+        })
+        .collect::<Vec<_>>();
+      triangles.sort();
+      triangles
+    };
+    let chunks = ChunkGrid {
+      origin:     glam::Vec3A::splat(-4.0),
+      chunk_size: 4.0,
+      min:        [0, 0, 0],
+      max:        [2, 2, 2],
+    };
+    let whole = ChunkGrid {
+      chunk_size: 8.0,
+      max: [1, 1, 1],
+      ..chunks
+    };
+    let split = triangles(mesh_sphere(&chunks, 2.3, |_| 3));
+    assert!(!split.is_empty());
+    assert_eq!(split, triangles(mesh_sphere(&whole, 2.3, |_| 4)));
+  }
+}
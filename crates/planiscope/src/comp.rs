@@ -1,17 +1,48 @@
-use fidget::{context::Node, Context};
+use anyhow::Result;
+use fidget::{
+  context::Node,
+  eval::{Family, Tape},
+  Context,
+};
 
 use crate::{
-  nso::nso_translate,
+  material::{MaterialTable, DEFAULT_MATERIAL_ID},
+  mesh::ColorMode,
+  nso::{
+    nso_mix, nso_mix_color, nso_nearest_weight, nso_smooth_weight,
+    nso_translate,
+  },
   shape::{Shape, ShapeLike},
 };
 
 type Position = [f32; 3];
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CompilationSettings {
   pub min_voxel_size: f32,
 }
 
+impl CompilationSettings {
+  /// The settings for meshing a region reaching `size` from its center along
+  /// each axis, with its smallest voxels at `max_depth` in the octree.
+  pub fn for_region(size: [f32; 3], max_depth: u8) -> Self {
+    let smallest_scale_dim = size.into_iter().fold(f32::INFINITY, f32::min);
+    CompilationSettings {
+      min_voxel_size: smallest_scale_dim * 2.0 / 2.0f32.powi(max_depth as i32),
+    }
+  }
+}
+
+/// The tapes a composition is meshed from.
+pub struct CompositionTapes<T: Family> {
+  pub solid:    Tape<T>,
+  /// The red, green and blue tapes, if colors are evaluated.
+  pub colors:   Option<[Tape<T>; 3]>,
+  pub material: Tape<T>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Composition {
   shapes: Vec<(Shape, Position)>,
 }
@@ -55,23 +86,131 @@ impl Composition {
     binary_shape_tree(shapes.to_vec(), ctx, BinaryShapeTreeCombinator::Min)
   }
 
+  /// Compiles the color fields of the composition, one per RGB channel. Each
+  /// point takes the color of the nearest shape.
   pub fn compile_color(
     &self,
     ctx: &mut Context,
     settings: &CompilationSettings,
+  ) -> [Node; 3] {
+    // compile a translated solid and color for each Shape
+    let shapes = self
+      .shapes
+      .iter()
+      .map(|(shape, pos)| {
+        let solid = shape.compile_solid(ctx, settings);
+        let solid = nso_translate(solid, *pos, ctx);
+        let color = shape.compile_color(ctx, settings);
+        let color = color.map(|color| nso_translate(color, *pos, ctx));
+        (solid, color)
+      })
+      .collect::<Vec<_>>();
+
+    let weight = |a, b, ctx: &mut Context| nso_smooth_weight(a, b, 0.0, ctx);
+    nearest_shape_tree(shapes, ctx, weight, nso_mix_color)
+      .unwrap_or_else(|| [ctx.constant(1.0); 3])
+  }
+
+  /// Compiles the material id field of the composition. Each point takes the
+  /// material of the nearest shape.
+  pub fn compile_material(
+    &self,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
   ) -> Node {
-    // compile a translated Node for each Shape
-    let shapes = &self
+    let shapes = self
       .shapes
       .iter()
       .map(|(shape, pos)| {
-        let a = shape.compile_color(ctx, settings);
-        nso_translate(a, *pos, ctx)
+        let solid = shape.compile_solid(ctx, settings);
+        let solid = nso_translate(solid, *pos, ctx);
+        let material = shape.compile_material(ctx, settings);
+        (solid, nso_translate(material, *pos, ctx))
       })
-      .collect::<Vec<Node>>();
+      .collect::<Vec<_>>();
+
+    // ids can't be blended, so each point takes exactly one of them
+    nearest_shape_tree(shapes, ctx, nso_nearest_weight, nso_mix)
+      .unwrap_or_else(|| ctx.constant(DEFAULT_MATERIAL_ID.into()))
+  }
+
+  /// Checks that every shape in the composition can be compiled. See
+  /// `Shape::check`.
+  pub fn check(&self) -> Result<()> {
+    for (shape, _) in &self.shapes {
+      shape.check()?;
+    }
+    Ok(())
+  }
+
+  /// Compiles the solid, color and material fields of the composition into
+  /// tapes for meshing, leaving out colors unless `colors` asks for them.
+  /// `transform` is applied to each field first, such as to move a region
+  /// into the unit cube with `nso_normalize_region`. Fails if `check` does.
+  pub fn compile_tapes<T: Family>(
+    &self,
+    settings: &CompilationSettings,
+    colors: ColorMode,
+    transform: impl Fn(Node, &mut Context) -> Node,
+  ) -> Result<CompositionTapes<T>> {
+    self.check()?;
+    let mut ctx = Context::new();
 
-    binary_shape_tree(shapes.to_vec(), ctx, BinaryShapeTreeCombinator::Max)
+    let solid = self.compile_solid(&mut ctx, settings);
+    let solid = transform(solid, &mut ctx);
+    let colors = match colors {
+      ColorMode::Vertex => {
+        let [r, g, b] = self
+          .compile_color(&mut ctx, settings)
+          .map(|node| transform(node, &mut ctx));
+        Some([ctx.get_tape(r)?, ctx.get_tape(g)?, ctx.get_tape(b)?])
+      }
+      ColorMode::Disabled => None,
+    };
+    let material = self.compile_material(&mut ctx, settings);
+    let material = transform(material, &mut ctx);
+
+    Ok(CompositionTapes {
+      solid: ctx.get_tape(solid)?,
+      colors,
+      material: ctx.get_tape(material)?,
+    })
+  }
+
+  /// Collects the materials used by the shapes in the composition, keyed by
+  /// id.
+  pub fn materials(&self) -> MaterialTable {
+    let mut table = MaterialTable::new();
+    for (shape, _) in &self.shapes {
+      shape.collect_materials(&mut table);
+    }
+    table
+  }
+}
+
+/// Merges pairs of shapes like `binary_shape_tree`, keeping the attributes of
+/// the nearer shape, or returns `None` if there are no shapes. `weight` gives
+/// how much of the first shape's attributes to keep, for `mix`.
+fn nearest_shape_tree<T: Copy>(
+  mut shapes: Vec<(Node, T)>,
+  ctx: &mut Context,
+  weight: impl Fn(Node, Node, &mut Context) -> Node,
+  mix: impl Fn(T, T, Node, &mut Context) -> T,
+) -> Option<T> {
+  while shapes.len() > 1 {
+    shapes = shapes
+      .chunks(2)
+      .map(|pair| match pair {
+        [(a, a_attr), (b, b_attr)] => {
+          let t = weight(*a, *b, ctx);
+          let attr = mix(*a_attr, *b_attr, t, ctx);
+          (ctx.min(*a, *b).unwrap(), attr)
+        }
+        _ => pair[0],
+      })
+      .collect();
   }
+  shapes.first().map(|(_, attr)| *attr)
 }
 
 #[allow(dead_code)]
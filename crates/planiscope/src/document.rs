@@ -0,0 +1,114 @@
+//! Versioned JSON and RON documents.
+//!
+//! Shapes, compositions and compilation settings can be stored as documents
+//! which wrap the value with a format version, so that tools can refuse to
+//! load documents written by an incompatible version. Both formats are
+//! pretty-printed, so stored shape trees diff cleanly.
+
+use anyhow::{Error, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// The version of the document format. This is bumped whenever the serialized
+/// representation of a shape changes incompatibly.
+pub const DOCUMENT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Document<'a, T> {
+  version: u32,
+  content: &'a T,
+}
+
+#[derive(Deserialize)]
+struct OwnedDocument<T> {
+  content: T,
+}
+
+/// The version tag alone, read before the content so that a document from
+/// another version is reported as such rather than as a parsing error.
+#[derive(Deserialize)]
+struct VersionTag {
+  version: u32,
+}
+
+fn check_version(tag: VersionTag) -> Result<()> {
+  if tag.version != DOCUMENT_VERSION {
+    return Err(Error::msg(format!(
+      "unsupported document version {}, expected {}",
+      tag.version, DOCUMENT_VERSION
+    )));
+  }
+  Ok(())
+}
+
+/// Serializes a value to a pretty-printed JSON document.
+pub fn to_json<T: Serialize>(value: &T) -> Result<String> {
+  let document = Document {
+    version: DOCUMENT_VERSION,
+    content: value,
+  };
+  Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Deserializes a value from a JSON document, checking its version.
+pub fn from_json<T: DeserializeOwned>(source: &str) -> Result<T> {
+  check_version(serde_json::from_str(source)?)?;
+  let document: OwnedDocument<T> = serde_json::from_str(source)?;
+  Ok(document.content)
+}
+
+/// Serializes a value to a pretty-printed RON document.
+pub fn to_ron<T: Serialize>(value: &T) -> Result<String> {
+  let document = Document {
+    version: DOCUMENT_VERSION,
+    content: value,
+  };
+  Ok(ron::ser::to_string_pretty(
+    &document,
+    ron::ser::PrettyConfig::default(),
+  )?)
+}
+
+/// Deserializes a value from a RON document, checking its version.
+pub fn from_ron<T: DeserializeOwned>(source: &str) -> Result<T> {
+  check_version(ron::from_str(source)?)?;
+  let document: OwnedDocument<T> = ron::from_str(source)?;
+  Ok(document.content)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{builder::*, comp::Composition, shape::Shape};
+
+  fn composition() -> Composition {
+    let shape = smooth_union(
+      recolor(sphere(1.0), 255, 0, 0),
+      translate(box_(1.0, 2.0, 1.0), 1.0, 0.0, 0.0),
+      0.25,
+    );
+    Composition::from(vec![(shape, [0.0, 1.0, 0.0])])
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let composition = composition();
+    // compositions can't be compared directly, so compare their documents
+    let json = to_json(&composition).unwrap();
+    let parsed: Composition = from_json(&json).unwrap();
+    assert_eq!(to_json(&parsed).unwrap(), json);
+    let ron = to_ron(&composition).unwrap();
+    let parsed: Composition = from_ron(&ron).unwrap();
+    assert_eq!(to_ron(&parsed).unwrap(), ron);
+  }
+
+  #[test]
+  fn test_version_is_checked() {
+    let json = to_json(&sphere(1.0)).unwrap();
+    let json = json.replace(
+      &format!("\"version\": {}", DOCUMENT_VERSION),
+      &format!("\"version\": {}", DOCUMENT_VERSION + 1),
+    );
+    let error = from_json::<Shape>(&json).unwrap_err();
+    assert!(error.to_string().contains("unsupported document version"));
+  }
+}
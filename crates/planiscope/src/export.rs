@@ -0,0 +1,451 @@
+//! Writers for common mesh file formats.
+//!
+//! Each writer includes the normals and vertex colors of a `FullMesh` where
+//! the format supports them. Meshes with flat normals (`normals: None`) are
+//! written without normals, and viewers compute them from the faces.
+
+use std::{
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+};
+
+use anyhow::{Error, Result};
+
+use crate::mesh::FullMesh;
+
+/// A mesh file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshFormat {
+  /// Wavefront OBJ, with vertex colors appended to each vertex position as is
+  /// common (though not standard).
+  Obj,
+  /// Binary STL. STL has no vertex attributes, so only face normals are
+  /// written.
+  Stl,
+  /// Binary little-endian PLY, with 8-bit vertex colors.
+  Ply,
+  /// glTF 2.0 JSON, with the binary buffer embedded as a data URI.
+  Gltf,
+  /// Binary glTF 2.0.
+  Glb,
+}
+
+impl MeshFormat {
+  /// Picks a format from a path's extension.
+  pub fn from_path(path: &Path) -> Option<Self> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+      "obj" => Some(MeshFormat::Obj),
+      "stl" => Some(MeshFormat::Stl),
+      "ply" => Some(MeshFormat::Ply),
+      "gltf" => Some(MeshFormat::Gltf),
+      "glb" => Some(MeshFormat::Glb),
+      _ => None,
+    }
+  }
+
+  /// Writes a mesh in this format.
+  pub fn write<W: Write>(&self, mesh: &FullMesh, writer: W) -> Result<()> {
+    match self {
+      MeshFormat::Obj => write_obj(mesh, writer),
+      MeshFormat::Stl => write_stl(mesh, writer),
+      MeshFormat::Ply => write_ply(mesh, writer),
+      MeshFormat::Gltf => write_gltf(mesh, writer),
+      MeshFormat::Glb => write_glb(mesh, writer),
+    }
+  }
+}
+
+/// Writes a mesh to a file, in the format given by the file's extension.
+pub fn save_mesh(mesh: &FullMesh, path: impl AsRef<Path>) -> Result<()> {
+  let path = path.as_ref();
+  let format = MeshFormat::from_path(path).ok_or_else(|| {
+    Error::msg(format!("unknown mesh format for {}", path.display()))
+  })?;
+  let mut writer = BufWriter::new(File::create(path)?);
+  format.write(mesh, &mut writer)?;
+  writer.flush()?;
+  Ok(())
+}
+
+/// Writes a mesh as Wavefront OBJ.
+pub fn write_obj<W: Write>(mesh: &FullMesh, mut writer: W) -> Result<()> {
+  writeln!(writer, "# generated by planiscope")?;
+  for (i, v) in mesh.vertices.iter().enumerate() {
+    match &mesh.colors {
+      Some(colors) => {
+        let c = colors[i];
+        writeln!(writer, "v {} {} {} {} {} {}", v.x, v.y, v.z, c.x, c.y, c.z)?
+      }
+      None => writeln!(writer, "v {} {} {}", v.x, v.y, v.z)?,
+    }
+  }
+  if let Some(normals) = &mesh.normals {
+    for n in normals {
+      let n = n.normalize_or_zero();
+      writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+    }
+  }
+  // obj indices start at 1
+  for t in &mesh.triangles {
+    let [a, b, c] = (*t + 1).to_array();
+    match mesh.normals {
+      Some(_) => writeln!(writer, "f {a}//{a} {b}//{b} {c}//{c}")?,
+      None => writeln!(writer, "f {a} {b} {c}")?,
+    }
+  }
+  Ok(())
+}
+
+/// Writes a mesh as binary STL.
+pub fn write_stl<W: Write>(mesh: &FullMesh, mut writer: W) -> Result<()> {
+  let mut header = [0u8; 80];
+  let title = b"generated by planiscope";
+  header[..title.len()].copy_from_slice(title);
+  writer.write_all(&header)?;
+  writer.write_all(&(mesh.triangles.len() as u32).to_le_bytes())?;
+  for t in &mesh.triangles {
+    let [a, b, c] = t.to_array().map(|i| mesh.vertices[i as usize]);
+    let normal = (b - a).cross(c - a).normalize_or_zero();
+    for v in [normal, a, b, c] {
+      for component in v.to_array() {
+        writer.write_all(&component.to_le_bytes())?;
+      }
+    }
+    // the attribute byte count, which is unused
+    writer.write_all(&[0, 0])?;
+  }
+  Ok(())
+}
+
+/// Writes a mesh as binary little-endian PLY.
+pub fn write_ply<W: Write>(mesh: &FullMesh, mut writer: W) -> Result<()> {
+  writeln!(writer, "ply")?;
+  writeln!(writer, "format binary_little_endian 1.0")?;
+  writeln!(writer, "comment generated by planiscope")?;
+  writeln!(writer, "element vertex {}", mesh.vertices.len())?;
+  for axis in ["x", "y", "z"] {
+    writeln!(writer, "property float {}", axis)?;
+  }
+  if mesh.normals.is_some() {
+    for axis in ["nx", "ny", "nz"] {
+      writeln!(writer, "property float {}", axis)?;
+    }
+  }
+  if mesh.colors.is_some() {
+    for channel in ["red", "green", "blue"] {
+      writeln!(writer, "property uchar {}", channel)?;
+    }
+  }
+  writeln!(writer, "element face {}", mesh.triangles.len())?;
+  writeln!(writer, "property list uchar uint vertex_indices")?;
+  writeln!(writer, "end_header")?;
+
+  for (i, v) in mesh.vertices.iter().enumerate() {
+    for component in v.to_array() {
+      writer.write_all(&component.to_le_bytes())?;
+    }
+    if let Some(normals) = &mesh.normals {
+      for component in normals[i].normalize_or_zero().to_array() {
+        writer.write_all(&component.to_le_bytes())?;
+      }
+    }
+    if let Some(colors) = &mesh.colors {
+      let c = colors[i]
+        .truncate()
+        .clamp(glam::Vec3::ZERO, glam::Vec3::ONE);
+      writer.write_all(&(c * 255.0).round().to_array().map(|c| c as u8))?;
+    }
+  }
+  for t in &mesh.triangles {
+    writer.write_all(&[3])?;
+    for i in t.to_array() {
+      writer.write_all(&i.to_le_bytes())?;
+    }
+  }
+  Ok(())
+}
+
+/// Writes a mesh as glTF 2.0 JSON, with its buffer embedded as base64. Fails
+/// for a mesh without triangles, which glTF can't hold.
+pub fn write_gltf<W: Write>(mesh: &FullMesh, mut writer: W) -> Result<()> {
+  let buffer = gltf_buffer(mesh)?;
+  let uri = format!(
+    "data:application/octet-stream;base64,{}",
+    base64(&buffer.data)
+  );
+  writer.write_all(gltf_json(mesh, &buffer, Some(&uri)).as_bytes())?;
+  Ok(())
+}
+
+/// Writes a mesh as binary glTF 2.0. Fails for a mesh without triangles, as
+/// with `write_gltf`.
+pub fn write_glb<W: Write>(mesh: &FullMesh, mut writer: W) -> Result<()> {
+  const JSON_CHUNK: u32 = 0x4E4F534A;
+  const BIN_CHUNK: u32 = 0x004E4942;
+
+  let buffer = gltf_buffer(mesh)?;
+  // both chunks must be 4-byte aligned, the json padded with spaces
+  let mut json = gltf_json(mesh, &buffer, None).into_bytes();
+  json.resize(json.len().next_multiple_of(4), b' ');
+  let mut data = buffer.data;
+  data.resize(data.len().next_multiple_of(4), 0);
+
+  let length = 12 + 8 + json.len() + 8 + data.len();
+  writer.write_all(b"glTF")?;
+  writer.write_all(&2u32.to_le_bytes())?;
+  writer.write_all(&(length as u32).to_le_bytes())?;
+  writer.write_all(&(json.len() as u32).to_le_bytes())?;
+  writer.write_all(&JSON_CHUNK.to_le_bytes())?;
+  writer.write_all(&json)?;
+  writer.write_all(&(data.len() as u32).to_le_bytes())?;
+  writer.write_all(&BIN_CHUNK.to_le_bytes())?;
+  writer.write_all(&data)?;
+  Ok(())
+}
+
+/// A glTF buffer, holding each attribute and then the indices, and the byte
+/// range of each.
+struct GltfBuffer {
+  data:   Vec<u8>,
+  /// The byte offset and length of each buffer view, in the order of the
+  /// accessors: positions, then normals and colors if present, then indices.
+  views:  Vec<(usize, usize)>,
+  /// The bounds of the vertex positions, which glTF requires.
+  bounds: Option<(glam::Vec3A, glam::Vec3A)>,
+}
+
+/// Lays out the buffer for a mesh. glTF requires every accessor and buffer
+/// to be non-empty, so a mesh without triangles is an error.
+fn gltf_buffer(mesh: &FullMesh) -> Result<GltfBuffer> {
+  if mesh.triangles.is_empty() {
+    return Err(Error::msg("glTF can't hold a mesh without triangles"));
+  }
+
+  let mut data = Vec::new();
+  let mut views = Vec::new();
+  let mut push_view = |data: &mut Vec<u8>, values: Vec<f32>| {
+    let start = data.len();
+    data.extend(values.into_iter().flat_map(f32::to_le_bytes));
+    views.push((start, data.len() - start));
+  };
+
+  push_view(
+    &mut data,
+    mesh.vertices.iter().flat_map(|v| v.to_array()).collect(),
+  );
+  if let Some(normals) = &mesh.normals {
+    push_view(
+      &mut data,
+      normals
+        .iter()
+        .flat_map(|n| n.normalize_or_zero().to_array())
+        .collect(),
+    );
+  }
+  if let Some(colors) = &mesh.colors {
+    push_view(
+      &mut data,
+      colors.iter().flat_map(|c| c.to_array()).collect(),
+    );
+  }
+  let start = data.len();
+  data.extend(
+    mesh
+      .triangles
+      .iter()
+      .flat_map(|t| t.to_array())
+      .flat_map(u32::to_le_bytes),
+  );
+  views.push((start, data.len() - start));
+
+  let bounds = mesh.vertices.iter().fold(None, |bounds, v| match bounds {
+    Some((min, max)) => Some((v.min(min), v.max(max))),
+    None => Some((*v, *v)),
+  });
+  Ok(GltfBuffer {
+    data,
+    views,
+    bounds,
+  })
+}
+
+/// Builds the glTF JSON for a mesh whose data is in `buffer`, which is either
+/// at `uri` or (for GLB) in the binary chunk.
+fn gltf_json(
+  mesh: &FullMesh,
+  buffer: &GltfBuffer,
+  uri: Option<&str>,
+) -> String {
+  const FLOAT: u32 = 5126;
+  const UNSIGNED_INT: u32 = 5125;
+  const ARRAY_BUFFER: u32 = 34962;
+  const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+  let vertex_count = mesh.vertices.len();
+  let mut accessors = Vec::new();
+  let mut attributes = Vec::new();
+
+  let bounds = match buffer.bounds {
+    Some((min, max)) => format!(
+      r#","min":[{},{},{}],"max":[{},{},{}]"#,
+      min.x, min.y, min.z, max.x, max.y, max.z
+    ),
+    None => String::new(),
+  };
+  attributes.push(format!(r#""POSITION":{}"#, accessors.len()));
+  accessors.push(format!(
+    r#"{{"bufferView":{},"componentType":{FLOAT},"count":{vertex_count},"type":"VEC3"{bounds}}}"#,
+    accessors.len()
+  ));
+  if mesh.normals.is_some() {
+    attributes.push(format!(r#""NORMAL":{}"#, accessors.len()));
+    accessors.push(format!(
+      r#"{{"bufferView":{},"componentType":{FLOAT},"count":{vertex_count},"type":"VEC3"}}"#,
+      accessors.len()
+    ));
+  }
+  if mesh.colors.is_some() {
+    attributes.push(format!(r#""COLOR_0":{}"#, accessors.len()));
+    accessors.push(format!(
+      r#"{{"bufferView":{},"componentType":{FLOAT},"count":{vertex_count},"type":"VEC4"}}"#,
+      accessors.len()
+    ));
+  }
+  let indices = accessors.len();
+  accessors.push(format!(
+    r#"{{"bufferView":{indices},"componentType":{UNSIGNED_INT},"count":{},"type":"SCALAR"}}"#,
+    mesh.triangles.len() * 3
+  ));
+
+  let buffer_views = buffer
+    .views
+    .iter()
+    .enumerate()
+    .map(|(i, (offset, length))| {
+      let target = if i == indices {
+        ELEMENT_ARRAY_BUFFER
+      } else {
+        ARRAY_BUFFER
+      };
+      format!(
+        r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{length},"target":{target}}}"#
+      )
+    })
+    .collect::<Vec<_>>();
+  let uri = match uri {
+    Some(uri) => format!(r#","uri":"{uri}""#),
+    None => String::new(),
+  };
+
+  format!(
+    concat!(
+      r#"{{"asset":{{"version":"2.0","generator":"planiscope"}},"#,
+      r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+      r#""meshes":[{{"primitives":[{{"attributes":{{{}}},"indices":{}}}]}}],"#,
+      r#""accessors":[{}],"bufferViews":[{}],"#,
+      r#""buffers":[{{"byteLength":{}{}}}]}}"#,
+    ),
+    attributes.join(","),
+    indices,
+    accessors.join(","),
+    buffer_views.join(","),
+    buffer.data.len(),
+    uri,
+  )
+}
+
+/// Encodes bytes as standard padded base64.
+fn base64(bytes: &[u8]) -> String {
+  const ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+  for chunk in bytes.chunks(3) {
+    let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+      group | (*byte as u32) << (16 - 8 * i)
+    });
+    for i in 0..4 {
+      if i <= chunk.len() {
+        let index = (group >> (18 - 6 * i)) & 0x3F;
+        encoded.push(ALPHABET[index as usize] as char);
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+  encoded
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn triangle() -> FullMesh {
+    FullMesh {
+      vertices:     vec![glam::Vec3A::ZERO, glam::Vec3A::X, glam::Vec3A::Y],
+      triangles:    vec![glam::UVec3::new(0, 1, 2)],
+      normals:      Some(vec![glam::Vec3A::Z; 3]),
+      colors:       Some(vec![glam::Vec4::new(1.0, 0.0, 0.0, 1.0); 3]),
+      material_ids: None,
+    }
+  }
+
+  #[test]
+  fn test_exporters() {
+    let mut obj = Vec::new();
+    write_obj(&triangle(), &mut obj).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    assert!(obj.contains("v 1 0 0 1 0 0\n"));
+    assert!(obj.contains("f 1//1 2//2 3//3\n"));
+
+    let mut stl = Vec::new();
+    write_stl(&triangle(), &mut stl).unwrap();
+    assert_eq!(stl.len(), 84 + 50);
+
+    let mut ply = Vec::new();
+    write_ply(&triangle(), &mut ply).unwrap();
+    let header_end = b"end_header\n";
+    let body = ply
+      .windows(header_end.len())
+      .position(|window| window == header_end)
+      .unwrap()
+      + header_end.len();
+    // three vertices of 6 floats and 3 color bytes each, then one face
+    assert_eq!(ply.len() - body, 3 * (6 * 4 + 3) + (1 + 3 * 4));
+
+    let mut glb = Vec::new();
+    write_glb(&triangle(), &mut glb).unwrap();
+    assert_eq!(&glb[..4], b"glTF");
+    assert_eq!(
+      u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+      glb.len()
+    );
+  }
+
+  #[test]
+  fn test_gltf_rejects_empty_mesh() {
+    let empty = FullMesh {
+      vertices:     Vec::new(),
+      triangles:    Vec::new(),
+      normals:      None,
+      colors:       None,
+      material_ids: None,
+    };
+    assert!(write_gltf(&empty, Vec::new()).is_err());
+    assert!(write_glb(&empty, Vec::new()).is_err());
+
+    let mut gltf = Vec::new();
+    write_gltf(&triangle(), &mut gltf).unwrap();
+    let gltf = String::from_utf8(gltf).unwrap();
+    assert!(gltf.contains(r#""count":3,"type":"SCALAR""#));
+  }
+
+  #[test]
+  fn test_base64() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+  }
+}
@@ -0,0 +1,866 @@
+//! Importing triangle meshes and heightmaps as sampled fields.
+
+use anyhow::{anyhow, Result};
+use fidget::{context::Node, Context};
+use glam::Vec3A;
+
+use crate::{mesh::FullMesh, nso::*};
+
+/// The number of empty cells kept around the mesh on each side of the grid,
+/// so that the surface is never on the edge of the grid.
+const SDF_GRID_PADDING: u32 = 2;
+
+/// The most cells a mesh can be sampled with along the longest side of its
+/// bounds. Sampling costs grow with the cube of the resolution, so this keeps
+/// a typo in a script from stalling it for hours.
+pub const SDF_GRID_MAX_RESOLUTION: u32 = 256;
+
+/// The most cells along each axis of a block of an `SdfGrid` that interpolates
+/// each of its samples.
+const SDF_GRID_BLOCK_CELLS: u32 = 4;
+
+/// A box of samples of an `SdfGrid`, as the first and last sample along each
+/// axis.
+#[derive(Debug, Clone, Copy)]
+struct SampleBox {
+  first: [u32; 3],
+  last:  [u32; 3],
+}
+
+/// A signed distance field sampled on a regular grid, negative inside.
+/// Between samples the field is interpolated trilinearly, and outside the grid
+/// it's extended by the distance to the grid's bounds.
+///
+/// The field compiles to the lowest of the fields of blocks of the grid. Blocks
+/// the surface can pass through interpolate each of their samples, while the
+/// rest interpolate only their corners. Each block's field rises steeply
+/// outside it, so the mesher's interval evaluation drops the blocks far from
+/// each region it looks at, and large grids stay quick to mesh.
+///
+/// Deserializing goes through `SdfGrid::new`, so a stored grid is checked
+/// like any other.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(try_from = "SdfGridFields")
+)]
+pub struct SdfGrid {
+  /// The position of the first sample.
+  pub origin:     [f32; 3],
+  /// The distance between neighbouring samples.
+  pub cell_size:  f32,
+  /// The number of samples along each axis.
+  pub resolution: [u32; 3],
+  /// The samples, with x varying fastest and z slowest.
+  pub values:     Vec<f32>,
+}
+
+/// The fields of an `SdfGrid` as stored, before they're checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SdfGridFields {
+  origin:     [f32; 3],
+  cell_size:  f32,
+  resolution: [u32; 3],
+  values:     Vec<f32>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SdfGridFields> for SdfGrid {
+  type Error = anyhow::Error;
+
+  fn try_from(fields: SdfGridFields) -> Result<Self> {
+    SdfGrid::new(
+      fields.origin,
+      fields.cell_size,
+      fields.resolution,
+      fields.values,
+    )
+  }
+}
+
+impl SdfGrid {
+  /// Creates a grid from its samples, checking that the cell size is
+  /// positive and that there's one value for each of the `resolution`
+  /// samples.
+  pub fn new(
+    origin: [f32; 3],
+    cell_size: f32,
+    resolution: [u32; 3],
+    values: Vec<f32>,
+  ) -> Result<Self> {
+    if !(cell_size > 0.0 && cell_size.is_finite()) {
+      return Err(anyhow!(
+        "grid cell size must be positive, got {}",
+        cell_size
+      ));
+    }
+    let count = resolution
+      .iter()
+      .try_fold(1_usize, |count, n| count.checked_mul(*n as usize));
+    if count != Some(values.len()) || values.is_empty() {
+      return Err(anyhow!(
+        "grid of {:?} samples doesn't match its {} values",
+        resolution,
+        values.len()
+      ));
+    }
+    Ok(SdfGrid {
+      origin,
+      cell_size,
+      resolution,
+      values,
+    })
+  }
+
+  /// Samples the signed distance to a triangle mesh, with `resolution` cells
+  /// along the longest side of its bounds, up to `SDF_GRID_MAX_RESOLUTION`.
+  /// The mesh doesn't need to be watertight: inside and outside are decided
+  /// by the generalized winding number, so small holes and overlapping pieces
+  /// are handled gracefully.
+  pub fn from_triangles(
+    vertices: &[Vec3A],
+    triangles: &[[u32; 3]],
+    resolution: u32,
+  ) -> Result<Self> {
+    if resolution == 0 || resolution > SDF_GRID_MAX_RESOLUTION {
+      return Err(anyhow!(
+        "grid resolution must be between 1 and {}, got {}",
+        SDF_GRID_MAX_RESOLUTION,
+        resolution
+      ));
+    }
+    let triangles = triangles
+      .iter()
+      .map(|t| {
+        let corner = |i: u32| {
+          vertices
+            .get(i as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("triangle references missing vertex {}", i))
+        };
+        Ok([corner(t[0])?, corner(t[1])?, corner(t[2])?])
+      })
+      .collect::<Result<Vec<_>>>()?;
+    if triangles.is_empty() {
+      return Err(anyhow!("mesh has no triangles"));
+    }
+
+    let (min, max) = triangles.iter().flatten().fold(
+      (Vec3A::splat(f32::INFINITY), Vec3A::splat(f32::NEG_INFINITY)),
+      |(min, max), v| (min.min(*v), max.max(*v)),
+    );
+    let cell_size = (max - min).max_element() / resolution as f32;
+    if !(cell_size > 0.0 && cell_size.is_finite()) {
+      return Err(anyhow!("mesh has no extent"));
+    }
+
+    let padding = SDF_GRID_PADDING as f32 * cell_size;
+    let origin = min - padding;
+    let counts = ((max - min) / cell_size).ceil().as_uvec3()
+      + glam::UVec3::splat(2 * SDF_GRID_PADDING + 1);
+
+    // each z slice is independent, so sample them in parallel
+    let slices = (0..counts.z).collect::<Vec<_>>();
+    let threads = std::thread::available_parallelism()
+      .map(|n| n.get())
+      .unwrap_or(1);
+    let chunk_size = slices.len().div_ceil(threads);
+    let values = std::thread::scope(|scope| {
+      let handles = slices
+        .chunks(chunk_size)
+        .map(|chunk| {
+          let triangles = &triangles;
+          scope.spawn(move || {
+            let mut values = Vec::new();
+            for z in chunk {
+              for y in 0..counts.y {
+                for x in 0..counts.x {
+                  let point = origin
+                    + Vec3A::new(x as f32, y as f32, *z as f32) * cell_size;
+                  values.push(signed_distance(point, triangles));
+                }
+              }
+            }
+            values
+          })
+        })
+        .collect::<Vec<_>>();
+      handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect()
+    });
+
+    Ok(SdfGrid {
+      origin: origin.to_array(),
+      cell_size,
+      resolution: counts.to_array(),
+      values,
+    })
+  }
+
+  /// Samples the signed distance to a mesh produced by planiscope (or
+  /// converted into a `FullMesh`). See `from_triangles`.
+  pub fn from_full_mesh(mesh: &FullMesh, resolution: u32) -> Result<Self> {
+    let triangles = mesh
+      .triangles
+      .iter()
+      .map(|t| t.to_array())
+      .collect::<Vec<_>>();
+    Self::from_triangles(&mesh.vertices, &triangles, resolution)
+  }
+
+  /// Samples the signed distance to the mesh in a Wavefront OBJ file. See
+  /// `from_triangles`.
+  pub fn from_obj(source: &str, resolution: u32) -> Result<Self> {
+    let (vertices, triangles) = parse_obj(source)?;
+    Self::from_triangles(&vertices, &triangles, resolution)
+  }
+
+  /// The position of the last sample.
+  fn max_corner(&self) -> [f32; 3] {
+    let mut corner = self.origin;
+    for (i, c) in corner.iter_mut().enumerate() {
+      *c += (self.resolution[i].max(1) - 1) as f32 * self.cell_size;
+    }
+    corner
+  }
+
+  /// The sample at a position in the grid.
+  fn sample(&self, index: [u32; 3]) -> f32 {
+    let [nx, ny, _] = self.resolution.map(|n| n as usize);
+    let [x, y, z] = index.map(|i| i as usize);
+    self.values[x + nx * (y + ny * z)]
+  }
+
+  /// The position of a sample.
+  fn sample_position(&self, index: [u32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|i| self.origin[i] + index[i] as f32 * self.cell_size)
+  }
+
+  /// Whether every sample in a box is on the same side of the surface, so that
+  /// the surface can't pass through it.
+  fn is_one_sided(&self, samples: SampleBox) -> bool {
+    let (mut inside, mut outside) = (false, false);
+    for z in samples.first[2]..=samples.last[2] {
+      for y in samples.first[1]..=samples.last[1] {
+        for x in samples.first[0]..=samples.last[0] {
+          if self.sample([x, y, z]) < 0.0 {
+            inside = true;
+          } else {
+            outside = true;
+          }
+        }
+      }
+    }
+    !(inside && outside)
+  }
+
+  /// The largest difference between neighbouring samples.
+  fn max_step(&self) -> f32 {
+    let mut max_step = 0.0_f32;
+    for z in 0..self.resolution[2] {
+      for y in 0..self.resolution[1] {
+        for x in 0..self.resolution[0] {
+          let value = self.sample([x, y, z]);
+          let index = [x, y, z];
+          for axis in 0..3 {
+            let mut next = index;
+            next[axis] += 1;
+            if next[axis] < self.resolution[axis] {
+              max_step = max_step.max((self.sample(next) - value).abs());
+            }
+          }
+        }
+      }
+    }
+    max_step
+  }
+
+  /// Compiles the interpolated field.
+  pub fn compile(&self, ctx: &mut Context) -> Node {
+    let [nx, ny, nz] = self.resolution.map(|n| n as usize);
+    if nx * ny * nz == 0
+      || self.values.len() != nx * ny * nz
+      || !(self.cell_size > 0.0 && self.cell_size.is_finite())
+    {
+      return ctx.constant(1.0);
+    }
+
+    // clamp the point into the grid, and measure how far it was moved
+    let axes = [ctx.x(), ctx.y(), ctx.z()];
+    let (clamped, outside) =
+      clamp_to_box(axes, self.origin, self.max_corner(), ctx);
+
+    // outside of each block its field rises faster than the interpolated
+    // field can, so the block containing a point is always the lowest there
+    let max_slope = 3.0_f32.sqrt() * self.max_step() / self.cell_size;
+    let slope = 2.0 * max_slope.max(1.0);
+    let samples = SampleBox {
+      first: [0; 3],
+      last:  self.resolution.map(|n| n - 1),
+    };
+    let inside = self.compile_box(samples, clamped, slope, ctx);
+    ctx.add(inside, outside).unwrap()
+  }
+
+  /// Compiles the field over a box of samples, as the lowest of the fields of
+  /// the blocks within it. A box the surface can't pass through is one block,
+  /// and any other is split until its blocks are at most
+  /// `SDF_GRID_BLOCK_CELLS` cells along each axis.
+  fn compile_box(
+    &self,
+    samples: SampleBox,
+    point: [Node; 3],
+    slope: f32,
+    ctx: &mut Context,
+  ) -> Node {
+    if self.is_one_sided(samples) {
+      return self.compile_block(samples, true, point, slope, ctx);
+    }
+    let cells = [0, 1, 2].map(|i| samples.last[i] - samples.first[i]);
+    if cells.iter().all(|cells| *cells <= SDF_GRID_BLOCK_CELLS) {
+      return self.compile_block(samples, false, point, slope, ctx);
+    }
+
+    // halve the box along each axis that's longer than a block
+    let mut boxes = vec![samples];
+    for (axis, cells) in cells.into_iter().enumerate() {
+      if cells > SDF_GRID_BLOCK_CELLS {
+        let middle = samples.first[axis] + cells / 2;
+        boxes = boxes
+          .into_iter()
+          .flat_map(|samples| {
+            let (mut lower, mut upper) = (samples, samples);
+            lower.last[axis] = middle;
+            upper.first[axis] = middle;
+            [lower, upper]
+          })
+          .collect();
+      }
+    }
+    let nodes = boxes
+      .into_iter()
+      .map(|samples| self.compile_box(samples, point, slope, ctx))
+      .collect::<Vec<_>>();
+    nodes
+      .into_iter()
+      .reduce(|a, b| ctx.min(a, b).unwrap())
+      .unwrap()
+  }
+
+  /// Compiles the field of one block: its samples, or only its corners if
+  /// `corners_only`, interpolated trilinearly inside the block, and rising by
+  /// `slope` with the distance outside it.
+  fn compile_block(
+    &self,
+    samples: SampleBox,
+    corners_only: bool,
+    point: [Node; 3],
+    slope: f32,
+    ctx: &mut Context,
+  ) -> Node {
+    let min = self.sample_position(samples.first);
+    let max = self.sample_position(samples.last);
+    let (clamped, outside) = clamp_to_box(point, min, max, ctx);
+
+    // trilinear interpolation is a sum of tent functions, one per sample,
+    // which factors into one tent per sample coordinate on each axis
+    let indices = (0..3)
+      .map(|i| {
+        let (first, last) = (samples.first[i], samples.last[i]);
+        if first == last {
+          vec![first]
+        } else if corners_only {
+          vec![first, last]
+        } else {
+          (first..=last).collect()
+        }
+      })
+      .collect::<Vec<_>>();
+    let tents = (0..3)
+      .map(|i| {
+        let spacing = match indices[i][..] {
+          [first, second, ..] => (second - first) as f32 * self.cell_size,
+          _ => self.cell_size,
+        };
+        indices[i]
+          .iter()
+          .map(|n| {
+            let center = self.origin[i] + *n as f32 * self.cell_size;
+            nso_tent(clamped[i], center, spacing, ctx)
+          })
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+
+    let mut total = None;
+    for (z, tent_z) in indices[2].iter().zip(&tents[2]) {
+      let mut plane = None;
+      for (y, tent_y) in indices[1].iter().zip(&tents[1]) {
+        let mut row = None;
+        for (x, tent_x) in indices[0].iter().zip(&tents[0]) {
+          let value = ctx.constant(self.sample([*x, *y, *z]).into());
+          let term = ctx.mul(*tent_x, value).unwrap();
+          row = Some(match row {
+            Some(row) => ctx.add(row, term).unwrap(),
+            None => term,
+          });
+        }
+        let term = ctx.mul(row.unwrap(), *tent_y).unwrap();
+        plane = Some(match plane {
+          Some(plane) => ctx.add(plane, term).unwrap(),
+          None => term,
+        });
+      }
+      let term = ctx.mul(plane.unwrap(), *tent_z).unwrap();
+      total = Some(match total {
+        Some(total) => ctx.add(total, term).unwrap(),
+        None => term,
+      });
+    }
+
+    let slope = ctx.constant(slope.into());
+    let outside = ctx.mul(outside, slope).unwrap();
+    ctx.add(total.unwrap(), outside).unwrap()
+  }
+}
+
+/// Clamps a point into a box, giving the clamped point and the distance it was
+/// moved.
+fn clamp_to_box(
+  point: [Node; 3],
+  min: [f32; 3],
+  max: [f32; 3],
+  ctx: &mut Context,
+) -> ([Node; 3], Node) {
+  let mut clamped = point;
+  let mut excess = point;
+  for i in 0..3 {
+    let lower = ctx.constant(min[i].into());
+    let upper = ctx.constant(max[i].into());
+    let value = ctx.max(point[i], lower).unwrap();
+    clamped[i] = ctx.min(value, upper).unwrap();
+    excess[i] = ctx.sub(point[i], clamped[i]).unwrap();
+  }
+  (clamped, nso_length(&excess, ctx))
+}
+
+/// A grid of heights, for `ShapeDef::Heightfield`. It can only be made with
+/// `Heightmap::new`, which deserializing goes through too, so every heightmap
+/// is checked.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(try_from = "HeightmapFields")
+)]
+pub struct Heightmap {
+  columns: u32,
+  rows:    u32,
+  values:  Vec<f32>,
+}
+
+/// The fields of a `Heightmap` as stored, before they're checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct HeightmapFields {
+  columns: u32,
+  rows:    u32,
+  values:  Vec<f32>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<HeightmapFields> for Heightmap {
+  type Error = anyhow::Error;
+
+  fn try_from(fields: HeightmapFields) -> Result<Self> {
+    Heightmap::new(fields.columns, fields.rows, fields.values)
+  }
+}
+
+impl Heightmap {
+  /// Creates a heightmap from `columns * rows` heights, with x varying
+  /// fastest.
+  pub fn new(columns: u32, rows: u32, values: Vec<f32>) -> Result<Self> {
+    if columns < 2 || rows < 2 {
+      return Err(anyhow!(
+        "heightmap needs at least 2 columns and rows, got {}x{}",
+        columns,
+        rows
+      ));
+    }
+    let count = columns as usize * rows as usize;
+    if values.len() != count {
+      return Err(anyhow!(
+        "heightmap of {}x{} needs {} values, got {}",
+        columns,
+        rows,
+        count,
+        values.len()
+      ));
+    }
+    Ok(Heightmap {
+      columns,
+      rows,
+      values,
+    })
+  }
+
+  /// Creates a heightmap from an 8-bit grayscale image, row by row from the
+  /// top, mapping black to `0` and white to `1`.
+  pub fn from_luma8(width: u32, height: u32, pixels: &[u8]) -> Result<Self> {
+    let values = pixels.iter().map(|p| *p as f32 / 255.0).collect();
+    Self::new(width, height, values)
+  }
+
+  /// The number of samples along x.
+  pub fn columns(&self) -> u32 {
+    self.columns
+  }
+
+  /// The number of samples along z.
+  pub fn rows(&self) -> u32 {
+    self.rows
+  }
+
+  /// The heights, with x varying fastest.
+  pub fn values(&self) -> &[f32] {
+    &self.values
+  }
+
+  /// The height at a sample.
+  pub fn get(&self, column: u32, row: u32) -> f32 {
+    self.values[(column + row * self.columns) as usize]
+  }
+
+  /// The steepest slope between neighbouring samples along x and along z, for
+  /// samples `spacing` apart.
+  pub fn max_slopes(&self, spacing: [f32; 2]) -> [f32; 2] {
+    let mut slopes = [0.0_f32; 2];
+    for row in 0..self.rows {
+      for column in 0..self.columns {
+        let height = self.get(column, row);
+        if column + 1 < self.columns {
+          let step = (self.get(column + 1, row) - height).abs();
+          slopes[0] = slopes[0].max(step / spacing[0]);
+        }
+        if row + 1 < self.rows {
+          let step = (self.get(column, row + 1) - height).abs();
+          slopes[1] = slopes[1].max(step / spacing[1]);
+        }
+      }
+    }
+    slopes
+  }
+
+  /// Compiles the height interpolated bilinearly between samples, with the
+  /// samples spread over `width` along x and `depth` along z, centered on the
+  /// origin. `x` and `z` should already be clamped to that footprint.
+  pub fn compile(
+    &self,
+    x: Node,
+    z: Node,
+    width: f32,
+    depth: f32,
+    ctx: &mut Context,
+  ) -> Node {
+    let spacing_x = width / (self.columns - 1) as f32;
+    let spacing_z = depth / (self.rows - 1) as f32;
+    let tents_x = (0..self.columns)
+      .map(|column| {
+        let center = -width / 2.0 + column as f32 * spacing_x;
+        nso_tent(x, center, spacing_x, ctx)
+      })
+      .collect::<Vec<_>>();
+
+    let mut total = None;
+    for row in 0..self.rows {
+      let center = -depth / 2.0 + row as f32 * spacing_z;
+      let tent_z = nso_tent(z, center, spacing_z, ctx);
+      let mut line = None;
+      for (column, tent_x) in tents_x.iter().enumerate() {
+        let value = ctx.constant(self.get(column as u32, row).into());
+        let term = ctx.mul(*tent_x, value).unwrap();
+        line = Some(match line {
+          Some(line) => ctx.add(line, term).unwrap(),
+          None => term,
+        });
+      }
+      let term = ctx.mul(line.unwrap(), tent_z).unwrap();
+      total = Some(match total {
+        Some(total) => ctx.add(total, term).unwrap(),
+        None => term,
+      });
+    }
+    total.unwrap()
+  }
+}
+
+/// Computes the signed distance from a point to a triangle soup: the distance
+/// to the closest triangle, negative where the winding number is over half.
+fn signed_distance(point: Vec3A, triangles: &[[Vec3A; 3]]) -> f32 {
+  let mut distance_sq = f32::INFINITY;
+  let mut solid_angle = 0.0;
+  for triangle in triangles {
+    let closest = closest_point_on_triangle(point, triangle);
+    distance_sq = distance_sq.min(point.distance_squared(closest));
+    solid_angle += triangle_solid_angle(point, triangle);
+  }
+  let winding = solid_angle / (4.0 * std::f32::consts::PI);
+  match winding.abs() > 0.5 {
+    true => -distance_sq.sqrt(),
+    false => distance_sq.sqrt(),
+  }
+}
+
+/// The signed solid angle subtended by a triangle, positive when its
+/// counter-clockwise side faces away from the point (Van Oosterom and
+/// Strackee).
+fn triangle_solid_angle(point: Vec3A, [a, b, c]: &[Vec3A; 3]) -> f32 {
+  let a = *a - point;
+  let b = *b - point;
+  let c = *c - point;
+  let (la, lb, lc) = (a.length(), b.length(), c.length());
+  let numerator = a.dot(b.cross(c));
+  let denominator =
+    la * lb * lc + a.dot(b) * lc + a.dot(c) * lb + b.dot(c) * la;
+  2.0 * numerator.atan2(denominator)
+}
+
+/// The closest point on a triangle to a point (Ericson, "Real-Time Collision
+/// Detection", 5.1.5).
+fn closest_point_on_triangle(p: Vec3A, [a, b, c]: &[Vec3A; 3]) -> Vec3A {
+  let (a, b, c) = (*a, *b, *c);
+  let ab = b - a;
+  let ac = c - a;
+  let ap = p - a;
+  let d1 = ab.dot(ap);
+  let d2 = ac.dot(ap);
+  if d1 <= 0.0 && d2 <= 0.0 {
+    return a;
+  }
+
+  let bp = p - b;
+  let d3 = ab.dot(bp);
+  let d4 = ac.dot(bp);
+  if d3 >= 0.0 && d4 <= d3 {
+    return b;
+  }
+
+  let vc = d1 * d4 - d3 * d2;
+  if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+    return a + ab * (d1 / (d1 - d3));
+  }
+
+  let cp = p - c;
+  let d5 = ab.dot(cp);
+  let d6 = ac.dot(cp);
+  if d6 >= 0.0 && d5 <= d6 {
+    return c;
+  }
+
+  let vb = d5 * d2 - d1 * d6;
+  if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+    return a + ac * (d2 / (d2 - d6));
+  }
+
+  let va = d3 * d6 - d5 * d4;
+  if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+    return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+  }
+
+  let denominator = va + vb + vc;
+  if denominator.abs() <= f32::EPSILON {
+    // a degenerate triangle, which the edge cases above have covered
+    return a;
+  }
+  a + ab * (vb / denominator) + ac * (vc / denominator)
+}
+
+/// Parses the vertices and faces of a Wavefront OBJ file, triangulating
+/// polygonal faces as fans. Everything else (normals, texture coordinates,
+/// groups and materials) is ignored.
+pub fn parse_obj(source: &str) -> Result<(Vec<Vec3A>, Vec<[u32; 3]>)> {
+  let mut vertices = Vec::new();
+  let mut triangles = Vec::new();
+  for (line_number, line) in source.lines().enumerate() {
+    let mut words = line.split_whitespace();
+    match words.next() {
+      Some("v") => {
+        let mut position = [0.0; 3];
+        for component in position.iter_mut() {
+          let word = words.next().and_then(|word| word.parse().ok());
+          *component = word.ok_or_else(|| {
+            anyhow!("invalid vertex on line {}", line_number + 1)
+          })?;
+        }
+        vertices.push(Vec3A::from_array(position));
+      }
+      Some("f") => {
+        let corners = words
+          .map(|word| {
+            // faces can be `v`, `v/vt`, `v//vn` or `v/vt/vn`
+            let index = word
+              .split('/')
+              .next()
+              .and_then(|index| index.parse::<i64>().ok())
+              .ok_or_else(|| {
+                anyhow!("invalid face on line {}", line_number + 1)
+              })?;
+            // indices are 1-based, and negative indices count back from the
+            // most recent vertex
+            let index = match index {
+              i if i > 0 => i - 1,
+              i if i < 0 => vertices.len() as i64 + i,
+              _ => -1,
+            };
+            if index < 0 || index >= vertices.len() as i64 {
+              return Err(anyhow!(
+                "face on line {} references a missing vertex",
+                line_number + 1
+              ));
+            }
+            Ok(index as u32)
+          })
+          .collect::<Result<Vec<_>>>()?;
+        for i in 1..corners.len().saturating_sub(1) {
+          triangles.push([corners[0], corners[i], corners[i + 1]]);
+        }
+      }
+      _ => {}
+    }
+  }
+  Ok((vertices, triangles))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const CUBE_OBJ: &str = "
+v -0.5 -0.5 -0.5
+v 0.5 -0.5 -0.5
+v 0.5 0.5 -0.5
+v -0.5 0.5 -0.5
+v -0.5 -0.5 0.5
+v 0.5 -0.5 0.5
+v 0.5 0.5 0.5
+v -0.5 0.5 0.5
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 3 4 8 7
+f 2 3 7 6
+f 1 5 8 4
+";
+
+  #[test]
+  fn test_obj_cube_grid() {
+    let grid = SdfGrid::from_obj(CUBE_OBJ, 8).unwrap();
+    let mut ctx = Context::new();
+    let node = grid.compile(&mut ctx);
+
+    let center = ctx.eval_xyz(node, 0.0, 0.0, 0.0).unwrap();
+    assert!((center + 0.5).abs() < 0.05);
+    let face = ctx.eval_xyz(node, 0.5, 0.1, 0.0).unwrap();
+    assert!(face.abs() < 0.05);
+    let near = ctx.eval_xyz(node, 0.0, 0.75, 0.0).unwrap();
+    assert!((near - 0.25).abs() < 0.05);
+    // past the grid's bounds, the distance keeps growing
+    let far = ctx.eval_xyz(node, 3.0, 0.0, 0.0).unwrap();
+    assert!((far - 2.5).abs() < 0.1);
+  }
+
+  #[test]
+  fn test_grid_blocks() {
+    // a finer grid is split into blocks, some of them interpolating only
+    // their corners, but every point keeps the side of the surface that
+    // interpolating all of the samples gives it
+    let grid = SdfGrid::from_obj(CUBE_OBJ, 16).unwrap();
+    let mut ctx = Context::new();
+    let node = grid.compile(&mut ctx);
+
+    let interpolate = |point: [f32; 3]| {
+      let mut value = 0.0;
+      let cell = [0, 1, 2].map(|i| {
+        let offset = (point[i] - grid.origin[i]) / grid.cell_size;
+        let first = (offset.floor() as u32).min(grid.resolution[i] - 2);
+        (first, offset - first as f32)
+      });
+      for corner in 0..8 {
+        let mut index = [0; 3];
+        let mut weight = 1.0;
+        for i in 0..3 {
+          let (first, t) = cell[i];
+          let upper = corner >> i & 1 == 1;
+          index[i] = first + upper as u32;
+          weight *= if upper { t } else { 1.0 - t };
+        }
+        value += weight * grid.sample(index);
+      }
+      value
+    };
+    for i in 0..=10 {
+      for j in 0..=10 {
+        let point = [-0.8 + i as f32 * 0.16, -0.8 + j as f32 * 0.16, 0.13];
+        let expected = interpolate(point);
+        let value = ctx
+          .eval_xyz(node, point[0].into(), point[1].into(), point[2].into())
+          .unwrap();
+        if expected.abs() > 1e-3 {
+          assert_eq!(value < 0.0, expected < 0.0, "at {:?}", point);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_grid_resolution_is_limited() {
+    assert!(SdfGrid::from_obj(CUBE_OBJ, 0).is_err());
+    let too_fine = SDF_GRID_MAX_RESOLUTION + 1;
+    assert!(SdfGrid::from_obj(CUBE_OBJ, too_fine).is_err());
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn test_grid_deserialize_is_checked() {
+    let grid = SdfGrid::from_obj(CUBE_OBJ, 4).unwrap();
+    let json = serde_json::to_string(&grid).unwrap();
+    assert_eq!(serde_json::from_str::<SdfGrid>(&json).unwrap(), grid);
+
+    for json in [
+      r#"{"origin": [0, 0, 0], "cell_size": 0, "resolution": [1, 1, 1],
+        "values": [0]}"#,
+      r#"{"origin": [0, 0, 0], "cell_size": 1, "resolution": [2, 2, 2],
+        "values": [0, 1, 1]}"#,
+      r#"{"origin": [0, 0, 0], "cell_size": 1, "resolution": [0, 0, 0],
+        "values": []}"#,
+    ] {
+      assert!(serde_json::from_str::<SdfGrid>(json).is_err());
+    }
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn test_heightmap_deserialize_is_checked() {
+    let json = r#"{"columns": 2, "rows": 2, "values": [0, 1, 1, 0]}"#;
+    let heightmap: Heightmap = serde_json::from_str(json).unwrap();
+    assert_eq!(heightmap.get(1, 0), 1.0);
+    assert_eq!((heightmap.columns(), heightmap.rows()), (2, 2));
+    let stored = serde_json::to_string(&heightmap).unwrap();
+    assert_eq!(
+      serde_json::from_str::<Heightmap>(&stored).unwrap(),
+      heightmap
+    );
+
+    for json in [
+      r#"{"columns": 0, "rows": 0, "values": []}"#,
+      r#"{"columns": 2, "rows": 2, "values": [0, 1, 1]}"#,
+    ] {
+      assert!(serde_json::from_str::<Heightmap>(json).is_err());
+    }
+  }
+}
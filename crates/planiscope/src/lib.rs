@@ -1,6 +1,16 @@
 pub mod builder;
+pub mod chunk;
 pub mod comp;
+#[cfg(feature = "serde")]
+pub mod document;
+pub mod export;
+pub mod import;
+pub mod lod;
+pub mod material;
 pub mod nso;
 pub mod mesh;
+#[cfg(feature = "pls")]
+pub mod pls;
+pub mod profile;
 pub mod rhai;
 pub mod shape;
@@ -0,0 +1,209 @@
+//! Levels of detail.
+//!
+//! A composition can be meshed at several depths to give a chain of levels of
+//! detail, from the most to the least detailed. In Bevy, a `LodGroup` entity
+//! holds the levels as children marked with `LodLevel`, and the `select_lods`
+//! system shows the one matching the group's distance from the camera.
+
+use anyhow::Result;
+use bevy_ecs::prelude::*;
+use bevy_hierarchy::Children;
+use bevy_render::{camera::Camera, view::Visibility};
+use bevy_transform::components::GlobalTransform;
+use fidget::eval::Family;
+
+use crate::{
+  comp::{CompilationSettings, Composition},
+  mesh::{CancelToken, FullMesh, MeshProgress, MeshSettings},
+  nso::nso_normalize_region,
+};
+
+/// A mesh of a composition at one level of detail.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lod {
+  /// The octree depth the mesh was built at.
+  pub max_depth: u8,
+  /// The mesh, in the composition's coordinates.
+  pub mesh:      FullMesh,
+}
+
+/// Meshes the region of a composition centered on `pos` and reaching `size`
+/// from it along each axis. The composition is compiled for the size of the
+/// smallest voxels in `settings`, and the mesh is in the composition's
+/// coordinates.
+pub fn mesh_region<T: Family>(
+  composition: &Composition,
+  pos: [f32; 3],
+  size: [f32; 3],
+  settings: &MeshSettings,
+  cancel: &CancelToken,
+  progress: impl FnMut(MeshProgress),
+) -> Result<FullMesh> {
+  let comp_settings =
+    CompilationSettings::for_region(size, settings.finest_depth);
+  let tapes = composition.compile_tapes::<T>(
+    &comp_settings,
+    settings.colors,
+    |node, ctx| nso_normalize_region(node, pos, size, ctx),
+  )?;
+
+  let mut mesh = FullMesh::mesh_new(
+    &tapes.solid,
+    tapes.colors.as_ref(),
+    Some(&tapes.material),
+    settings,
+    cancel,
+    progress,
+  )?;
+  mesh.denormalize(pos.into(), size.into());
+  Ok(mesh)
+}
+
+/// Meshes a composition at each of `depths`, giving a chain of levels of
+/// detail. Each level is compiled for its own voxel size, so details too small
+/// for a level are abbreviated instead of meshed. `progress` is called with
+/// the index of the level being meshed.
+pub fn mesh_lods<T: Family>(
+  composition: &Composition,
+  pos: [f32; 3],
+  size: [f32; 3],
+  depths: &[u8],
+  settings: &MeshSettings,
+  cancel: &CancelToken,
+  mut progress: impl FnMut(usize, MeshProgress),
+) -> Result<Vec<Lod>> {
+  depths
+    .iter()
+    .enumerate()
+    .map(|(level, depth)| {
+      let settings = settings
+        .finest_depth(*depth)
+        .coarsest_depth(settings.coarsest_depth.min(*depth));
+      let mesh = mesh_region::<T>(
+        composition,
+        pos,
+        size,
+        &settings,
+        cancel,
+        |latest| progress(level, latest),
+      )?;
+      Ok(Lod {
+        max_depth: *depth,
+        mesh,
+      })
+    })
+    .collect()
+}
+
+/// The depths of a chain of `count` levels of detail, starting at `max_depth`
+/// and one shallower each level, stopping at `min_depth`.
+pub fn lod_depths(max_depth: u8, min_depth: u8, count: usize) -> Vec<u8> {
+  (min_depth..=max_depth).rev().take(count).collect()
+}
+
+/// Picks the level of detail for something `distance` from the camera, where
+/// `distances[i]` is the furthest level `i` is shown. Anything beyond every
+/// distance gets the last level.
+pub fn lod_for_distance(distances: &[f32], distance: f32) -> usize {
+  distances
+    .iter()
+    .position(|max_distance| distance <= *max_distance)
+    .unwrap_or(distances.len().saturating_sub(1))
+}
+
+/// An entity whose children are its levels of detail, each marked with a
+/// `LodLevel`.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct LodGroup {
+  /// The furthest each level is shown from the camera, as used by
+  /// `lod_for_distance`.
+  pub distances: Vec<f32>,
+}
+
+/// Marks a child of a `LodGroup` as one of its levels of detail, `0` being
+/// the most detailed. Several children can share a level, such as one per
+/// material.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LodLevel(pub usize);
+
+/// Shows the level of detail of each `LodGroup` that matches its distance
+/// from the nearest active camera, and hides the others.
+pub fn select_lods(
+  cameras: Query<(&Camera, &GlobalTransform)>,
+  groups: Query<(&LodGroup, &GlobalTransform, &Children)>,
+  mut levels: Query<(&LodLevel, &mut Visibility)>,
+) {
+  let cameras = cameras
+    .iter()
+    .filter(|(camera, _)| camera.is_active)
+    .map(|(_, transform)| transform.translation())
+    .collect::<Vec<_>>();
+  if cameras.is_empty() {
+    return;
+  }
+
+  for (group, transform, children) in &groups {
+    let distance = cameras
+      .iter()
+      .map(|camera| camera.distance(transform.translation()))
+      .fold(f32::INFINITY, f32::min);
+    let shown = lod_for_distance(&group.distances, distance);
+
+    let mut children = levels.iter_many_mut(children);
+    while let Some((level, mut visibility)) = children.fetch_next() {
+      let wanted = if level.0 == shown {
+        Visibility::Inherited
+      } else {
+        Visibility::Hidden
+      };
+      // only write on changes, to keep change detection quiet
+      if *visibility != wanted {
+        *visibility = wanted;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bevy_hierarchy::BuildWorldChildren;
+  use glam::Vec3;
+
+  use super::*;
+
+  #[test]
+  fn test_lod_selection() {
+    assert_eq!(lod_depths(6, 3, 3), vec![6, 5, 4]);
+    assert_eq!(lod_depths(6, 5, 3), vec![6, 5]);
+
+    let distances = [10.0, 20.0, 40.0];
+    assert_eq!(lod_for_distance(&distances, 5.0), 0);
+    assert_eq!(lod_for_distance(&distances, 20.0), 1);
+    assert_eq!(lod_for_distance(&distances, 100.0), 2);
+    assert_eq!(lod_for_distance(&[], 100.0), 0);
+  }
+
+  #[test]
+  fn test_select_lods() {
+    let mut world = World::new();
+    world.spawn((Camera::default(), GlobalTransform::default()));
+    let near = world.spawn((LodLevel(0), Visibility::Inherited)).id();
+    let far = world.spawn((LodLevel(1), Visibility::Inherited)).id();
+    world
+      .spawn((
+        LodGroup {
+          distances: vec![10.0, 20.0],
+        },
+        GlobalTransform::from_translation(Vec3::new(15.0, 0.0, 0.0)),
+      ))
+      .push_children(&[near, far]);
+
+    let mut schedule = Schedule::default();
+    schedule.add_systems(select_lods);
+    schedule.run(&mut world);
+
+    assert_eq!(world.get::<Visibility>(near), Some(&Visibility::Hidden));
+    assert_eq!(world.get::<Visibility>(far), Some(&Visibility::Inherited));
+  }
+}
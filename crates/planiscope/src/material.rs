@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+/// The physically based surface properties of a material. Shapes are tagged
+/// with a material id by `UnaryOp::Material`, and the id is carried through
+/// compilation so that meshes can be split by material.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Material {
+  /// The perceptual roughness of the surface, from `0.0` (mirror-like) to
+  /// `1.0` (fully rough).
+  pub roughness: f32,
+  /// How metallic the surface is, from `0.0` to `1.0`.
+  pub metallic:  f32,
+  /// The linear RGB color emitted by the surface.
+  pub emissive:  [f32; 3],
+}
+
+/// The id of the material used by shapes that haven't been given one.
+pub const DEFAULT_MATERIAL_ID: u32 = 0;
+
+impl Default for Material {
+  fn default() -> Self {
+    Material {
+      roughness: 0.5,
+      metallic:  0.0,
+      emissive:  [0.0, 0.0, 0.0],
+    }
+  }
+}
+
+/// The materials used by a shape or composition, keyed by id.
+pub type MaterialTable = HashMap<u32, Material>;
@@ -1,78 +1,363 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+};
+
 use bevy_render::mesh::Mesh as BevyMesh;
 use fidget::{
   eval::{Family, Tape},
   mesh::{Mesh as FidgetMesh, Octree, Settings},
 };
 
+use crate::material::DEFAULT_MATERIAL_ID;
+
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FullMesh {
-  pub vertices:  Vec<glam::Vec3A>,
-  pub triangles: Vec<glam::UVec3>,
-  pub normals:   Option<Vec<glam::Vec3A>>,
-  pub colors:    Option<Vec<glam::Vec4>>,
+  pub vertices:     Vec<glam::Vec3A>,
+  pub triangles:    Vec<glam::UVec3>,
+  pub normals:      Option<Vec<glam::Vec3A>>,
+  pub colors:       Option<Vec<glam::Vec4>>,
+  /// The material id of each vertex, if the mesh was built with a material
+  /// tape.
+  pub material_ids: Option<Vec<u32>>,
+}
+
+/// How the normals of a mesh are computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalsMode {
+  /// Evaluates the gradient of the surface at each vertex.
+  #[default]
+  Smooth,
+  /// Leaves normals to be computed from each face, for a faceted look.
+  Flat,
+}
+
+/// Whether a mesh gets vertex colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+  /// Evaluates the color tapes at each vertex.
+  #[default]
+  Vertex,
+  /// Skips evaluating colors, even if color tapes are given.
+  Disabled,
+}
+
+/// Settings for building a mesh with `FullMesh::mesh_new`.
+///
+/// Depths are octree depths: the coarsest depth sets the size of the largest
+/// voxels, and the finest depth the size of the smallest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshSettings {
+  /// The number of threads used to build the octree.
+  pub threads:        u8,
+  /// The octree depth of the largest voxels.
+  pub coarsest_depth: u8,
+  /// The octree depth of the smallest voxels.
+  pub finest_depth:   u8,
+  pub normals:        NormalsMode,
+  pub colors:         ColorMode,
+  /// Triangles with a vertex outside of these bounds, in the normalized
+  /// region, are pruned. `None` keeps every triangle.
+  pub prune_bounds:   Option<glam::Vec3A>,
+}
+
+impl Default for MeshSettings {
+  fn default() -> Self {
+    let threads =
+      std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    MeshSettings {
+      threads:        u8::try_from(threads).unwrap_or(u8::MAX),
+      coarsest_depth: 0,
+      finest_depth:   6,
+      normals:        NormalsMode::default(),
+      colors:         ColorMode::default(),
+      prune_bounds:   Some(glam::Vec3A::ONE),
+    }
+  }
+}
+
+impl MeshSettings {
+  pub fn threads(mut self, threads: u8) -> Self {
+    self.threads = threads.max(1);
+    self
+  }
+
+  pub fn coarsest_depth(mut self, depth: u8) -> Self {
+    self.coarsest_depth = depth;
+    self
+  }
+
+  pub fn finest_depth(mut self, depth: u8) -> Self {
+    self.finest_depth = depth;
+    self
+  }
+
+  pub fn normals(mut self, normals: NormalsMode) -> Self {
+    self.normals = normals;
+    self
+  }
+
+  pub fn colors(mut self, colors: ColorMode) -> Self {
+    self.colors = colors;
+    self
+  }
+
+  pub fn prune_bounds(mut self, bounds: Option<glam::Vec3A>) -> Self {
+    self.prune_bounds = bounds;
+    self
+  }
+
+  fn octree_settings(&self) -> Settings {
+    // fidget names depths by the size of the voxels rather than by how deep
+    // they are in the octree: its `min_depth` is where the smallest voxels
+    // are.
+    Settings {
+      threads:   self.threads,
+      min_depth: self.finest_depth,
+      max_depth: self.coarsest_depth,
+    }
+  }
+}
+
+/// A phase of building a mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshPhase {
+  /// Building the octree from the solid tape.
+  Octree,
+  /// Walking the octree to build the triangles.
+  DualWalk,
+  /// Evaluating smooth normals at each vertex.
+  Normals,
+  /// Evaluating colors at each vertex.
+  Colors,
+  /// Evaluating material ids at each vertex.
+  Materials,
+}
+
+/// The progress of a mesh build, reported to the callback given to
+/// `FullMesh::mesh_new`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshProgress {
+  pub phase:    MeshPhase,
+  /// How far through the phase the build is, from `0.0` to `1.0`. The octree
+  /// phases only report their start.
+  pub fraction: f32,
+}
+
+/// A token for cancelling a mesh build from another thread. Clones share the
+/// same state, so cancelling any clone cancels the build.
+///
+/// Builds only check the token between steps, so a build that is building
+/// or walking an octree runs that phase to the end before it stops.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+/// The error returned by `FullMesh::mesh_new` when its build was cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "meshing was cancelled")
+  }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// The number of vertices evaluated between checks for cancellation.
+pub(crate) const EVAL_CHUNK_SIZE: usize = 16384;
+
+/// Reports the progress of a mesh build, checking for cancellation each time.
+struct Reporter<'a, F> {
+  cancel:   &'a CancelToken,
+  progress: F,
+}
+
+impl<F: FnMut(MeshProgress)> Reporter<'_, F> {
+  fn report(
+    &mut self,
+    phase: MeshPhase,
+    fraction: f32,
+  ) -> Result<(), Cancelled> {
+    if self.cancel.is_cancelled() {
+      return Err(Cancelled);
+    }
+    (self.progress)(MeshProgress { phase, fraction });
+    Ok(())
+  }
+
+  /// Evaluates a value for each vertex in chunks, reporting progress between
+  /// them.
+  fn eval_in_chunks<R>(
+    &mut self,
+    phase: MeshPhase,
+    [xs, ys, zs]: &[Vec<f32>; 3],
+    mut eval: impl FnMut(&[f32], &[f32], &[f32]) -> Vec<R>,
+  ) -> Result<Vec<R>, Cancelled> {
+    log::debug!("evaluating {:?}", phase);
+    let mut values = Vec::with_capacity(xs.len());
+    for start in (0..xs.len()).step_by(EVAL_CHUNK_SIZE) {
+      self.report(phase, start as f32 / xs.len() as f32)?;
+      let range = start..(start + EVAL_CHUNK_SIZE).min(xs.len());
+      values.extend(eval(&xs[range.clone()], &ys[range.clone()], &zs[range]));
+    }
+    self.report(phase, 1.0)?;
+    Ok(values)
+  }
 }
 
 impl FullMesh {
+  /// Meshes the surface of a solid tape, evaluating colors and material ids at
+  /// each vertex if their tapes are given.
+  ///
+  /// `progress` is called as the build advances. The build stops with
+  /// `Cancelled` at the next phase or chunk of vertices once `cancel` is
+  /// cancelled. Fidget can't interrupt building or walking the octree, which
+  /// are usually the slowest phases, so a cancelled build still finishes the
+  /// one it's in. `chunk::mesh_chunks` samples without an octree and checks
+  /// throughout, for quicker cancelling.
   pub fn mesh_new<T: Family>(
     solid_tape: &Tape<T>,
-    color_tape: Option<&Tape<T>>,
-    smooth_normals: bool,
-    max_depth: u8,
-    min_depth: u8,
-  ) -> Self {
-    let settings = Settings {
-      threads:   6,
-      // no this is not a typo. I think that these are named opposite of what they should be. the smallest voxel, represented by `min_depth` is at the maximum depth. the largest voxel, represented by `max_depth` is at the minimum depth.
-      min_depth: max_depth,
-      max_depth: min_depth,
-    };
+    color_tapes: Option<&[Tape<T>; 3]>,
+    material_tape: Option<&Tape<T>>,
+    settings: &MeshSettings,
+    cancel: &CancelToken,
+    progress: impl FnMut(MeshProgress),
+  ) -> Result<Self, Cancelled> {
+    let octree_settings = settings.octree_settings();
+    let mut reporter = Reporter { cancel, progress };
 
-    println!("building octree");
-    let octree = Octree::build::<T>(solid_tape, settings);
-    let fidget_mesh = octree.walk_dual(settings);
-    println!("octree built");
+    log::debug!("building octree");
+    reporter.report(MeshPhase::Octree, 0.0)?;
+    let octree = Octree::build::<T>(solid_tape, octree_settings);
+    reporter.report(MeshPhase::DualWalk, 0.0)?;
+    let fidget_mesh = octree.walk_dual(octree_settings);
+    log::debug!("octree built");
 
-    println!("transforming vertices");
+    let axes = vertex_axes(&fidget_mesh);
     let vertices = fidget_mesh
       .vertices
       .iter()
       .map(|v| glam::Vec3A::new(v.x, v.y, v.z))
       .collect();
-    println!("vertices transformed");
-
-    println!("transforming triangles");
     let triangles = fidget_mesh
       .triangles
       .iter()
       .map(|t| glam::UVec3::new(t[0] as u32, t[1] as u32, t[2] as u32))
       .collect();
-    println!("triangles transformed");
-    
-    let normals = match smooth_normals {
-      true => {
-        println!("calculating normals from surface");
-        let normals = implicit_normals(&fidget_mesh, solid_tape);
-        println!("normals calculated");
-        Some(normals)
-      }
-      false => None,
+
+    let normals = match settings.normals {
+      NormalsMode::Smooth => Some(reporter.eval_in_chunks(
+        MeshPhase::Normals,
+        &axes,
+        |xs, ys, zs| eval_normals(solid_tape, xs, ys, zs),
+      )?),
+      NormalsMode::Flat => None,
     };
-    
-    let colors = match color_tape {
-      Some(color_tape) => {
-        println!("calculating colors from surface");
-        let colors = implicit_colors(&fidget_mesh, color_tape);
-        println!("colors calculated");
-        Some(colors)
-      }
+
+    let colors = match (settings.colors, color_tapes) {
+      (ColorMode::Vertex, Some(color_tapes)) => Some(reporter.eval_in_chunks(
+        MeshPhase::Colors,
+        &axes,
+        |xs, ys, zs| eval_colors(color_tapes, xs, ys, zs),
+      )?),
+      _ => None,
+    };
+
+    let material_ids = match material_tape {
+      Some(material_tape) => Some(reporter.eval_in_chunks(
+        MeshPhase::Materials,
+        &axes,
+        |xs, ys, zs| eval_material_ids(material_tape, xs, ys, zs),
+      )?),
       None => None,
     };
 
-    FullMesh {
+    let mut mesh = FullMesh {
       vertices,
       triangles,
       normals,
       colors,
+      material_ids,
+    };
+    if let Some(bounds) = settings.prune_bounds {
+      mesh.prune(bounds);
+    }
+    log::debug!(
+      "mesh built with {} vertices and {} triangles",
+      mesh.vertices.len(),
+      mesh.triangles.len()
+    );
+    Ok(mesh)
+  }
+
+  /// Splits the mesh into one submesh per material id, each with only the
+  /// vertices its triangles use. A triangle whose vertices disagree takes the
+  /// id shared by two of them, or the first vertex's id. A mesh without
+  /// material ids is returned whole with the default id.
+  pub fn split_by_material(self) -> Vec<(u32, FullMesh)> {
+    let Some(material_ids) = &self.material_ids else {
+      return vec![(DEFAULT_MATERIAL_ID, self)];
+    };
+
+    let mut triangles_by_id: BTreeMap<u32, Vec<glam::UVec3>> = BTreeMap::new();
+    for triangle in &self.triangles {
+      let [a, b, c] = triangle.to_array().map(|i| material_ids[i as usize]);
+      let id = if b == c && a != b { b } else { a };
+      triangles_by_id.entry(id).or_default().push(*triangle);
+    }
+
+    triangles_by_id
+      .into_iter()
+      .map(|(id, triangles)| (id, self.submesh(&triangles)))
+      .collect()
+  }
+
+  /// Builds a mesh from a subset of this mesh's triangles, keeping only the
+  /// vertices they use.
+  pub(crate) fn submesh(&self, triangles: &[glam::UVec3]) -> FullMesh {
+    let mut remap: HashMap<u32, u32> = HashMap::new();
+    let mut used = Vec::new();
+    let triangles = triangles
+      .iter()
+      .map(|triangle| {
+        glam::UVec3::from_array(triangle.to_array().map(|i| {
+          *remap.entry(i).or_insert_with(|| {
+            used.push(i as usize);
+            used.len() as u32 - 1
+          })
+        }))
+      })
+      .collect();
+
+    fn pick<A: Copy>(values: &[A], used: &[usize]) -> Vec<A> {
+      used.iter().map(|i| values[*i]).collect()
+    }
+    FullMesh {
+      vertices: pick(&self.vertices, &used),
+      triangles,
+      normals: self.normals.as_ref().map(|n| pick(n, &used)),
+      colors: self.colors.as_ref().map(|c| pick(c, &used)),
+      material_ids: self.material_ids.as_ref().map(|m| pick(m, &used)),
     }
   }
 
@@ -82,21 +367,20 @@ impl FullMesh {
     });
   }
 
-  pub fn prune(&mut self) {
-    // prune triangles outside of the -1 to 1 range on any axis
-    const MESH_BLEED: [f32; 3] = [1.0, 1.0, 1.0];
+  /// Removes triangles with a vertex outside of `bounds` on any axis.
+  pub fn prune(&mut self, bounds: glam::Vec3A) {
     let violating_verts = self
       .vertices
       .iter()
       .enumerate()
-      .filter(|(_, v)| v.abs().cmpgt(MESH_BLEED.into()).any())
+      .filter(|(_, v)| v.abs().cmpgt(bounds).any())
       .map(|(i, _)| i)
       .collect::<Vec<usize>>();
 
     self.triangles.retain(|t| {
       violating_verts
         .iter()
-        .all(|i| !t.to_array().iter().any(|x| *x == (*i as u32)))
+        .all(|i| !t.to_array().contains(&(*i as u32)))
     });
   }
 }
@@ -145,28 +429,49 @@ impl From<FullMesh> for BevyMesh {
   }
 }
 
-// TODO: refactor this to actually use bulk evaluators
+impl From<FullMesh> for Vec<(u32, BevyMesh)> {
+  /// Converts a mesh into one Bevy mesh per material id, so that each can be
+  /// rendered with its own material.
+  fn from(mesh: FullMesh) -> Self {
+    mesh
+      .split_by_material()
+      .into_iter()
+      .map(|(id, mesh)| (id, mesh.into()))
+      .collect()
+  }
+}
+
+/// Splits the vertices of a mesh by axis, for the slice evaluators.
+fn vertex_axes(mesh: &FidgetMesh) -> [Vec<f32>; 3] {
+  [
+    mesh.vertices.iter().map(|v| v.x).collect(),
+    mesh.vertices.iter().map(|v| v.y).collect(),
+    mesh.vertices.iter().map(|v| v.z).collect(),
+  ]
+}
+
+/// Evaluates the normal of each vertex from the gradient of the surface.
 pub fn implicit_normals<T: Family>(
   mesh: &FidgetMesh,
   tape: &Tape<T>,
 ) -> Vec<glam::Vec3A> {
-  let eval = tape.new_grad_slice_evaluator();
-  let mut normals: Vec<glam::Vec3A> = vec![];
-  let grad = eval.eval(
-    &mesh.vertices.iter().map(|v| v.x).collect::<Vec<_>>(),
-    &mesh.vertices.iter().map(|v| v.y).collect::<Vec<_>>(),
-    &mesh.vertices.iter().map(|v| v.z).collect::<Vec<_>>(),
-    &[],
-  );
-  match grad {
+  let [xs, ys, zs] = vertex_axes(mesh);
+  eval_normals(tape, &xs, &ys, &zs)
+}
+
+pub(crate) fn eval_normals<T: Family>(
+  tape: &Tape<T>,
+  xs: &[f32],
+  ys: &[f32],
+  zs: &[f32],
+) -> Vec<glam::Vec3A> {
+  match tape.new_grad_slice_evaluator().eval(xs, ys, zs, &[]) {
     Err(_) => panic!("normal evaluation failed"),
-    Ok(grad) => {
-      for g in grad {
-        normals.push(glam::Vec3A::new(g.dx, g.dy, g.dz));
-      }
-    }
+    Ok(grad) => grad
+      .into_iter()
+      .map(|g| glam::Vec3A::new(g.dx, g.dy, g.dz))
+      .collect(),
   }
-  normals
 }
 
 pub fn flat_normals(triangles: Vec<glam::UVec3>, vertices: Vec<glam::Vec3A>) -> Vec<glam::Vec3A> {
@@ -183,48 +488,160 @@ pub fn flat_normals(triangles: Vec<glam::UVec3>, vertices: Vec<glam::Vec3A>) ->
   normals
 }
 
-// TODO: refactor this to actually use bulk evaluators
+/// Evaluates the color of each vertex from one tape per RGB channel.
 pub fn implicit_colors<T: Family>(
   mesh: &FidgetMesh,
-  tape: &Tape<T>,
+  tapes: &[Tape<T>; 3],
+) -> Vec<glam::Vec4> {
+  let [xs, ys, zs] = vertex_axes(mesh);
+  eval_colors(tapes, &xs, &ys, &zs)
+}
+
+pub(crate) fn eval_colors<T: Family>(
+  tapes: &[Tape<T>; 3],
+  xs: &[f32],
+  ys: &[f32],
+  zs: &[f32],
 ) -> Vec<glam::Vec4> {
-  let eval = tape.new_float_slice_evaluator();
-  let mut colors: Vec<glam::Vec4> = vec![];
-
-  let grad = eval.eval(
-    &mesh.vertices.iter().map(|v| v.x).collect::<Vec<_>>(),
-    &mesh.vertices.iter().map(|v| v.y).collect::<Vec<_>>(),
-    &mesh.vertices.iter().map(|v| v.z).collect::<Vec<_>>(),
-    &[],
-  );
-
-  match grad {
-    Err(_) => panic!("color evaluation failed"),
-    Ok(grad) => {
-      for g in grad {
-        colors.push(transform_implicit_color(g));
-      }
+  let channels = tapes.each_ref().map(|tape| {
+    match tape.new_float_slice_evaluator().eval(xs, ys, zs, &[]) {
+      Err(_) => panic!("color evaluation failed"),
+      Ok(channel) => channel,
     }
-  }
+  });
 
-  colors
+  (0..xs.len())
+    .map(|i| {
+      glam::Vec4::new(
+        channels[0][i].clamp(0.0, 1.0),
+        channels[1][i].clamp(0.0, 1.0),
+        channels[2][i].clamp(0.0, 1.0),
+        1.0,
+      )
+    })
+    .collect()
 }
 
-fn transform_implicit_color(val: f32) -> glam::Vec4 {
-  // we offset the hue by a bit when it gets set to avoid sampling red when
-  // sampling noise
-  if val < 0.1 {
-    return glam::Vec4::new(1.0, 1.0, 1.0, 1.0);
+/// Evaluates the material id of each vertex, rounding to the nearest id.
+pub fn implicit_material_ids<T: Family>(
+  mesh: &FidgetMesh,
+  tape: &Tape<T>,
+) -> Vec<u32> {
+  let [xs, ys, zs] = vertex_axes(mesh);
+  eval_material_ids(tape, &xs, &ys, &zs)
+}
+
+pub(crate) fn eval_material_ids<T: Family>(
+  tape: &Tape<T>,
+  xs: &[f32],
+  ys: &[f32],
+  zs: &[f32],
+) -> Vec<u32> {
+  match tape.new_float_slice_evaluator().eval(xs, ys, zs, &[]) {
+    Err(_) => panic!("material evaluation failed"),
+    Ok(ids) => ids
+      .into_iter()
+      .map(|id| id.round().max(0.0) as u32)
+      .collect(),
   }
+}
 
-  // put it back in the normal range
-  let val = (val - 0.1) / 0.9;
+#[cfg(test)]
+mod tests {
+  use fidget::Context;
 
-  let val = val * (256_u32.pow(3)) as f32;
-  // bit shift to get the original values
-  let red = ((val as u32) >> 16) as f32;
-  let green = (((val as u32) << 16) >> 24) as f32;
-  let blue = (((val as u32) << 24) >> 24) as f32;
+  use super::*;
+  use crate::nso::nso_normalize_region;
 
-  glam::Vec4::new(red / 255.0, green / 255.0, blue / 255.0, 1.0)
+  #[test]
+  fn test_mesh_settings() {
+    let settings = MeshSettings::default()
+      .threads(0)
+      .coarsest_depth(2)
+      .finest_depth(7);
+    assert_eq!(settings.threads, 1);
+    let octree_settings = settings.octree_settings();
+    assert_eq!(octree_settings.min_depth, 7);
+    assert_eq!(octree_settings.max_depth, 2);
+  }
+
+  #[test]
+  fn test_cancelled_build() {
+    let mut ctx = fidget::Context::new();
+    let x = ctx.x();
+    let tape: Tape<fidget::vm::Eval> = ctx.get_tape(x).unwrap();
+    // cancelling a clone cancels the build
+    let cancel = CancelToken::new();
+    cancel.clone().cancel();
+    let mut reported = false;
+    let mesh = FullMesh::mesh_new(
+      &tape,
+      None,
+      None,
+      &MeshSettings::default(),
+      &cancel,
+      |_| reported = true,
+    );
+    assert_eq!(mesh.err(), Some(Cancelled));
+    assert!(!reported);
+  }
+
+  #[test]
+  fn test_prune() {
+    let mut mesh = FullMesh {
+      vertices:     vec![
+        glam::Vec3A::ZERO,
+        glam::Vec3A::X,
+        glam::Vec3A::Y,
+        glam::Vec3A::new(0.0, 0.0, 1.5),
+      ],
+      triangles:    vec![glam::UVec3::new(0, 1, 2), glam::UVec3::new(0, 1, 3)],
+      normals:      None,
+      colors:       None,
+      material_ids: None,
+    };
+    mesh.prune(glam::Vec3A::ONE);
+    assert_eq!(mesh.triangles, vec![glam::UVec3::new(0, 1, 2)]);
+  }
+
+  #[test]
+  fn test_denormalize_matches_normalized_region() {
+    // an off-center, stretched region, where scaling before or after moving
+    // lands in different places
+    let pos = [3.0, -2.0, 0.5];
+    let size = [2.0, 4.0, 0.5];
+    let mut ctx = Context::new();
+    let x = ctx.x();
+    let y = ctx.y();
+    let z = ctx.z();
+    let sum = ctx.add(x, y).unwrap();
+    let sum = ctx.add(sum, z).unwrap();
+    let normalized = nso_normalize_region(sum, pos, size, &mut ctx);
+
+    let mut mesh = FullMesh {
+      vertices:     vec![
+        glam::Vec3A::ZERO,
+        glam::Vec3A::ONE,
+        glam::Vec3A::new(-0.5, 0.25, 1.0),
+      ],
+      triangles:    vec![glam::UVec3::new(0, 1, 2)],
+      normals:      None,
+      colors:       None,
+      material_ids: None,
+    };
+    let expected = mesh
+      .vertices
+      .iter()
+      .map(|v| {
+        let v = v.as_dvec3();
+        ctx.eval_xyz(normalized, v.x, v.y, v.z).unwrap()
+      })
+      .collect::<Vec<_>>();
+    mesh.denormalize(pos.into(), size.into());
+    for (vertex, expected) in mesh.vertices.iter().zip(expected) {
+      let sum = (vertex.x + vertex.y + vertex.z) as f64;
+      assert!((sum - expected).abs() < 1e-5);
+    }
+    assert_eq!(mesh.vertices[0], glam::Vec3A::from(pos));
+  }
 }
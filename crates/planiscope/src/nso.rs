@@ -4,9 +4,12 @@
 
 use fidget::{context::Node, Context};
 
-/// Performs a CSG union between two nodes.
+use crate::shape::Axis;
+
+/// Performs a CSG union between two nodes. Fields are negative inside, so
+/// this is the lower of the two.
 pub fn nso_union(a: Node, b: Node, ctx: &mut Context) -> Node {
-  ctx.max(a, b).unwrap()
+  ctx.min(a, b).unwrap()
 }
 
 /// Performs a CSG difference between two nodes.
@@ -15,19 +18,177 @@ pub fn nso_difference(a: Node, b: Node, ctx: &mut Context) -> Node {
   ctx.max(a, b).unwrap()
 }
 
-/// Performs a CSG intersection between two nodes.
+/// Performs a CSG intersection between two nodes, the higher of the two.
 pub fn nso_intersection(a: Node, b: Node, ctx: &mut Context) -> Node {
-  ctx.min(a, b).unwrap()
+  ctx.max(a, b).unwrap()
 }
 
 /// Performs a CSG union between two nodes, and preserves the value of the first
-/// node where they intersect.
+/// node where they intersect. Outside the first node this is a plain union.
 pub fn nso_replacement(a: Node, b: Node, ctx: &mut Context) -> Node {
   let neg_a = ctx.neg(a).unwrap();
-  let b = ctx.min(b, neg_a).unwrap();
+  let b = ctx.max(b, neg_a).unwrap();
   ctx.min(a, b).unwrap()
 }
 
+/// Computes the blend factor of a polynomial smooth minimum with radius `k`:
+/// `max(k - |a - b|, 0) / k`. This is `1` where the nodes are equal and falls
+/// to `0` once they differ by `k` or more.
+fn nso_smooth_blend(a: Node, b: Node, k: f32, ctx: &mut Context) -> Node {
+  let k = ctx.constant(k.into());
+  let zero = ctx.constant(0.0);
+  let diff = ctx.sub(a, b).unwrap();
+  let diff = ctx.abs(diff).unwrap();
+  let h = ctx.sub(k, diff).unwrap();
+  let h = ctx.max(h, zero).unwrap();
+  ctx.div(h, k).unwrap()
+}
+
+/// Computes the polynomial smooth minimum of two nodes with blend radius `k`.
+/// Falls back to a hard minimum when `k` is not positive.
+pub fn nso_smooth_min(a: Node, b: Node, k: f32, ctx: &mut Context) -> Node {
+  let min = ctx.min(a, b).unwrap();
+  if k <= 0.0 {
+    return min;
+  }
+  let h = nso_smooth_blend(a, b, k, ctx);
+  let h_sq = ctx.square(h).unwrap();
+  let factor = ctx.constant((k * 0.25).into());
+  let offset = ctx.mul(h_sq, factor).unwrap();
+  ctx.sub(min, offset).unwrap()
+}
+
+/// Computes the polynomial smooth maximum of two nodes with blend radius `k`.
+/// Falls back to a hard maximum when `k` is not positive.
+pub fn nso_smooth_max(a: Node, b: Node, k: f32, ctx: &mut Context) -> Node {
+  let neg_a = ctx.neg(a).unwrap();
+  let neg_b = ctx.neg(b).unwrap();
+  let min = nso_smooth_min(neg_a, neg_b, k, ctx);
+  ctx.neg(min).unwrap()
+}
+
+/// Performs a smooth CSG union between two nodes, filleting the seam with
+/// radius `k`.
+pub fn nso_smooth_union(a: Node, b: Node, k: f32, ctx: &mut Context) -> Node {
+  nso_smooth_min(a, b, k, ctx)
+}
+
+/// Performs a smooth CSG difference between two nodes, filleting the seam
+/// with radius `k`.
+pub fn nso_smooth_difference(
+  a: Node,
+  b: Node,
+  k: f32,
+  ctx: &mut Context,
+) -> Node {
+  let b = ctx.neg(b).unwrap();
+  nso_smooth_max(a, b, k, ctx)
+}
+
+/// Performs a smooth CSG intersection between two nodes, filleting the seam
+/// with radius `k`.
+pub fn nso_smooth_intersection(
+  a: Node,
+  b: Node,
+  k: f32,
+  ctx: &mut Context,
+) -> Node {
+  nso_smooth_max(a, b, k, ctx)
+}
+
+/// Computes how much of `a` should show through a smooth blend of `a` and `b`
+/// with radius `k`: `1` where `a` dominates, `0` where `b` dominates, and
+/// linear across the fillet.
+pub fn nso_smooth_weight(a: Node, b: Node, k: f32, ctx: &mut Context) -> Node {
+  let diff = ctx.sub(b, a).unwrap();
+  let zero = ctx.constant(0.0);
+  let one = ctx.constant(1.0);
+  let t = if k <= 0.0 {
+    let steep_slope = ctx.constant(1000.0);
+    ctx.mul(diff, steep_slope).unwrap()
+  } else {
+    let half_over_k = ctx.constant((0.5 / k).into());
+    ctx.mul(diff, half_over_k).unwrap()
+  };
+  let half = ctx.constant(0.5);
+  let t = ctx.add(t, half).unwrap();
+  let t = ctx.max(t, zero).unwrap();
+  ctx.min(t, one).unwrap()
+}
+
+/// Computes a weight of exactly `1` where `a` is smaller than or equal to `b`
+/// and `0` where it's larger, for selecting between attributes that can't be
+/// blended, like material ids. Only differences too small to be normal floats
+/// fall between the two.
+pub fn nso_nearest_weight(a: Node, b: Node, ctx: &mut Context) -> Node {
+  let diff = ctx.sub(b, a).unwrap();
+  let zero = ctx.constant(0.0);
+  let one = ctx.constant(1.0);
+  let steepest_slope = ctx.constant(f32::MAX.into());
+  let t = ctx.mul(diff, steepest_slope).unwrap();
+  let t = ctx.add(t, one).unwrap();
+  let t = ctx.max(t, zero).unwrap();
+  ctx.min(t, one).unwrap()
+}
+
+/// Linearly interpolates between two nodes: `a` where `t` is `1` and `b` where
+/// `t` is `0`.
+pub fn nso_mix(a: Node, b: Node, t: Node, ctx: &mut Context) -> Node {
+  let diff = ctx.sub(a, b).unwrap();
+  let scaled = ctx.mul(diff, t).unwrap();
+  ctx.add(b, scaled).unwrap()
+}
+
+/// Computes the Euclidean length of a vector whose components are nodes.
+pub fn nso_length(components: &[Node], ctx: &mut Context) -> Node {
+  let mut sum = ctx.square(components[0]).unwrap();
+  for component in &components[1..] {
+    let sq = ctx.square(*component).unwrap();
+    sum = ctx.add(sum, sq).unwrap();
+  }
+  ctx.sqrt(sum).unwrap()
+}
+
+/// Intersects fields that each vary along one of several mutually orthogonal
+/// directions (e.g. the slabs of a box, or the side and caps of a cylinder).
+/// Unlike a plain maximum, this gives the exact distance outside the corners.
+pub fn nso_orthogonal_intersection(
+  components: &[Node],
+  ctx: &mut Context,
+) -> Node {
+  let zero = ctx.constant(0.0);
+  let mut inside = components[0];
+  for component in &components[1..] {
+    inside = ctx.max(inside, *component).unwrap();
+  }
+  let inside = ctx.min(inside, zero).unwrap();
+  let outside = components
+    .iter()
+    .map(|component| ctx.max(*component, zero).unwrap())
+    .collect::<Vec<_>>();
+  let outside = nso_length(&outside, ctx);
+  ctx.add(inside, outside).unwrap()
+}
+
+/// Computes a tent function of `value`, which is `1` at `center` and falls
+/// linearly to `0` at a distance of `width`.
+pub fn nso_tent(
+  value: Node,
+  center: f32,
+  width: f32,
+  ctx: &mut Context,
+) -> Node {
+  let center = ctx.constant(center.into());
+  let inv_width = ctx.constant((1.0 / width).into());
+  let zero = ctx.constant(0.0);
+  let one = ctx.constant(1.0);
+  let offset = ctx.sub(value, center).unwrap();
+  let offset = ctx.abs(offset).unwrap();
+  let offset = ctx.mul(offset, inv_width).unwrap();
+  let tent = ctx.sub(one, offset).unwrap();
+  ctx.max(tent, zero).unwrap()
+}
+
 /// Translates a node by `pos`.
 pub fn nso_translate(shape: Node, pos: [f32; 3], ctx: &mut Context) -> Node {
   let x = ctx.x();
@@ -56,7 +217,719 @@ pub fn nso_scale(shape: Node, scale: [f32; 3], ctx: &mut Context) -> Node {
   ctx.remap_xyz(shape, [new_x, new_y, new_z]).unwrap()
 }
 
-/// Transform volume of size `size` centered at `pos` to a unit cube.
+/// Inverts an affine transform given as a column-major 4x4 matrix (the layout
+/// used by `glam::Mat4::from_cols_array`). Returns `None` if the matrix is
+/// singular.
+pub fn invert_affine(matrix: [f32; 16]) -> Option<glam::Mat4> {
+  let matrix = glam::Mat4::from_cols_array(&matrix);
+  let det = matrix.determinant();
+  if det == 0.0 || !det.is_finite() {
+    return None;
+  }
+  let inverse = matrix.inverse();
+  inverse.is_finite().then_some(inverse)
+}
+
+/// Remaps the x, y and z of a node through the affine part of `matrix`. The
+/// bottom row of the matrix is ignored.
+pub fn nso_remap_affine(
+  shape: Node,
+  matrix: glam::Mat4,
+  ctx: &mut Context,
+) -> Node {
+  let x = ctx.x();
+  let y = ctx.y();
+  let z = ctx.z();
+  let rows = [matrix.row(0), matrix.row(1), matrix.row(2)];
+  let [new_x, new_y, new_z] = rows.map(|row| {
+    let row_x = ctx.constant(row.x.into());
+    let row_y = ctx.constant(row.y.into());
+    let row_z = ctx.constant(row.z.into());
+    let row_w = ctx.constant(row.w.into());
+    let term_x = ctx.mul(x, row_x).unwrap();
+    let term_y = ctx.mul(y, row_y).unwrap();
+    let term_z = ctx.mul(z, row_z).unwrap();
+    let sum = ctx.add(term_x, term_y).unwrap();
+    let sum = ctx.add(sum, term_z).unwrap();
+    ctx.add(sum, row_w).unwrap()
+  });
+  ctx.remap_xyz(shape, [new_x, new_y, new_z]).unwrap()
+}
+
+/// Applies an affine transform to a node, by remapping through the inverse of
+/// `matrix`. `matrix` is column-major. Returns `None` if the matrix is
+/// singular.
+pub fn nso_matrix_transform(
+  shape: Node,
+  matrix: [f32; 16],
+  ctx: &mut Context,
+) -> Option<Node> {
+  let inverse = invert_affine(matrix)?;
+  Some(nso_remap_affine(shape, inverse, ctx))
+}
+
+/// Rotates a node by `rotation`.
+pub fn nso_rotate(
+  shape: Node,
+  rotation: glam::Quat,
+  ctx: &mut Context,
+) -> Node {
+  let inverse = glam::Mat4::from_quat(rotation.inverse());
+  nso_remap_affine(shape, inverse, ctx)
+}
+
+/// The distance from the origin over which repeating operations are exact.
+/// Folding space out to this distance takes one `abs` per doubling, and f32
+/// precision degrades far from the origin, so this is kept modest.
+pub const NSO_REPEAT_EXTENT: f32 = 1024.0;
+
+/// Computes a triangle wave of a node with amplitude `amplitude` and period
+/// `4 * amplitude`. The wave follows the node where it lies within
+/// `[-amplitude, amplitude]` and reflects back and forth beyond that. It is
+/// built from nested `abs` folds, and is exact for node values within
+/// `extent` of zero.
+pub fn nso_triangle_wave(
+  value: Node,
+  amplitude: f32,
+  extent: f32,
+  ctx: &mut Context,
+) -> Node {
+  // shift so the wave is even about zero, peaking at `2 * amplitude`
+  let amplitude_node = ctx.constant(amplitude.into());
+  let shifted = ctx.add(value, amplitude_node).unwrap();
+  let mut folded = ctx.abs(shifted).unwrap();
+
+  // each fold about a multiple of the period halves the range to cover
+  let period = 4.0 * amplitude;
+  let mut levels = 0;
+  while period * 2.0_f32.powi(levels) < extent + amplitude {
+    levels += 1;
+  }
+  for level in (0..levels).rev() {
+    let fold = ctx.constant((period * 2.0_f32.powi(level)).into());
+    let moved = ctx.sub(folded, fold).unwrap();
+    folded = ctx.abs(moved).unwrap();
+  }
+
+  // the remaining range is a single period
+  let peak = ctx.constant((2.0 * amplitude).into());
+  let from_peak = ctx.sub(folded, peak).unwrap();
+  let from_peak = ctx.abs(from_peak).unwrap();
+  let wave = ctx.sub(peak, from_peak).unwrap();
+  ctx.sub(wave, amplitude_node).unwrap()
+}
+
+/// How steeply, in distance per period, `nso_repeat` pushes a copy away where
+/// its cell index isn't exact. It only has to outweigh the shape's own
+/// distances there, so it is kept well above any sensible shape size.
+const NSO_REPEAT_CUTOFF: f32 = 1000.0;
+
+/// Places `shape` at the lattice copies chosen per axis and takes the union.
+/// Each axis has either its coordinate unchanged, or two candidate local
+/// coordinates, relative to the nearest even and the nearest odd copy, along
+/// with a cutoff that is positive where that coordinate isn't exact.
+fn nso_repeat_copies(
+  shape: Node,
+  axes: [Vec<(Node, Option<Node>)>; 3],
+  ctx: &mut Context,
+) -> Node {
+  let mut union: Option<Node> = None;
+  for (x, x_cutoff) in &axes[0] {
+    for (y, y_cutoff) in &axes[1] {
+      for (z, z_cutoff) in &axes[2] {
+        let mut copy = ctx.remap_xyz(shape, [*x, *y, *z]).unwrap();
+        for cutoff in [x_cutoff, y_cutoff, z_cutoff].into_iter().flatten() {
+          copy = ctx.max(copy, *cutoff).unwrap();
+        }
+        union = Some(match union {
+          Some(union) => ctx.min(union, copy).unwrap(),
+          None => copy,
+        });
+      }
+    }
+  }
+  union.unwrap()
+}
+
+/// The candidate local coordinates for one repeated axis, given its lattice
+/// coordinate `u` in periods. See `nso_repeat_copies`.
+fn nso_repeat_axis(
+  u: Node,
+  period: f32,
+  extent: f32,
+  ctx: &mut Context,
+) -> Vec<(Node, Option<Node>)> {
+  let [even, odd, distance] = nso_lattice_points(u, extent, ctx);
+  let period_node = ctx.constant(period.into());
+  let slope = ctx.constant((period * NSO_REPEAT_CUTOFF).into());
+
+  // each point is exact up to `1 - snap` cells away, so start cutting it off
+  // a snap before that, leaving room for the cutoff to rise
+  let limit = ctx.constant((1.0 - 2.0 * NSO_LATTICE_SNAP).into());
+  let even_cutoff = ctx.sub(distance, limit).unwrap();
+  let even_cutoff = ctx.mul(even_cutoff, slope).unwrap();
+  let one = ctx.constant(1.0);
+  let odd_distance = ctx.sub(one, distance).unwrap();
+  let odd_cutoff = ctx.sub(odd_distance, limit).unwrap();
+  let odd_cutoff = ctx.mul(odd_cutoff, slope).unwrap();
+
+  let from_even = ctx.sub(u, even).unwrap();
+  let from_even = ctx.mul(from_even, period_node).unwrap();
+  let from_odd = ctx.sub(u, odd).unwrap();
+  let from_odd = ctx.mul(from_odd, period_node).unwrap();
+  vec![(from_even, Some(even_cutoff)), (from_odd, Some(odd_cutoff))]
+}
+
+/// Repeats a node through space with spacing `period` on each axis. An axis
+/// with a period of zero is not repeated.
+///
+/// The graph has no `floor` to wrap space with, so each point is measured
+/// against its nearest even and nearest odd copy from `nso_lattice_points`,
+/// and the union taken. Each of those is only exact up to `1 - 2 *
+/// NSO_LATTICE_SNAP` periods away, and is cut off beyond that, so parts of a
+/// shape reaching further from its own copy are lost. This costs two copies
+/// of the shape per repeated axis.
+pub fn nso_repeat(shape: Node, period: [f32; 3], ctx: &mut Context) -> Node {
+  let axes = [ctx.x(), ctx.y(), ctx.z()];
+  let choices = std::array::from_fn(|i| {
+    if period[i] > 0.0 {
+      let scale = ctx.constant((1.0 / period[i]).into());
+      let u = ctx.mul(axes[i], scale).unwrap();
+      nso_repeat_axis(u, period[i], NSO_REPEAT_EXTENT / period[i], ctx)
+    } else {
+      vec![(axes[i], None)]
+    }
+  });
+  nso_repeat_copies(shape, choices, ctx)
+}
+
+/// Repeats a node `count` times along each axis with spacing `period`,
+/// centering the copies on the origin. An axis with a count below 2 or a
+/// period of zero is not repeated. Beyond the end copies, only the end copy
+/// is kept. See `nso_repeat`.
+pub fn nso_repeat_finite(
+  shape: Node,
+  period: [f32; 3],
+  count: [u32; 3],
+  ctx: &mut Context,
+) -> Node {
+  let axes = [ctx.x(), ctx.y(), ctx.z()];
+  let choices = std::array::from_fn(|i| {
+    if count[i] < 2 || period[i] <= 0.0 {
+      return vec![(axes[i], None)];
+    }
+    let last = (count[i] - 1) as f32;
+
+    // move the first copy to zero and measure in periods, finding the copies
+    // only between the end copies
+    let half_span = ctx.constant((last * period[i] / 2.0).into());
+    let shifted = ctx.add(axes[i], half_span).unwrap();
+    let scale = ctx.constant((1.0 / period[i]).into());
+    let u = ctx.mul(shifted, scale).unwrap();
+    let zero = ctx.constant(0.0);
+    let last_node = ctx.constant(last.into());
+    let clamped = ctx.max(u, zero).unwrap();
+    let clamped = ctx.min(clamped, last_node).unwrap();
+    let mut copies = nso_repeat_axis(clamped, period[i], last, ctx);
+
+    // beyond the end copies, carry on from the end copies
+    let overshoot = ctx.sub(u, clamped).unwrap();
+    let period_node = ctx.constant(period[i].into());
+    let overshoot = ctx.mul(overshoot, period_node).unwrap();
+    for (local, _) in &mut copies {
+      *local = ctx.add(*local, overshoot).unwrap();
+    }
+    copies
+  });
+  nso_repeat_copies(shape, choices, ctx)
+}
+
+/// Computes the sine of a node. The node is folded into `[-π/2, π/2]` with
+/// `nso_triangle_wave` and then evaluated with an odd polynomial, so the
+/// result is accurate to about `1e-6` for node values within `extent` of zero.
+pub fn nso_sin(value: Node, extent: f32, ctx: &mut Context) -> Node {
+  // taylor coefficients of sin(x) / x, in powers of x^2
+  const COEFFICIENTS: [f32; 6] = [
+    1.0,
+    -1.0 / 6.0,
+    1.0 / 120.0,
+    -1.0 / 5040.0,
+    1.0 / 362880.0,
+    -1.0 / 39916800.0,
+  ];
+  let reduced =
+    nso_triangle_wave(value, std::f32::consts::FRAC_PI_2, extent, ctx);
+  let reduced_sq = ctx.square(reduced).unwrap();
+  let mut poly = ctx.constant(COEFFICIENTS[COEFFICIENTS.len() - 1].into());
+  for coefficient in COEFFICIENTS.iter().rev().skip(1) {
+    let coefficient = ctx.constant((*coefficient).into());
+    poly = ctx.mul(poly, reduced_sq).unwrap();
+    poly = ctx.add(poly, coefficient).unwrap();
+  }
+  ctx.mul(poly, reduced).unwrap()
+}
+
+/// Computes the cosine of a node, accurate for node values within `extent` of
+/// zero. See `nso_sin`.
+pub fn nso_cos(value: Node, extent: f32, ctx: &mut Context) -> Node {
+  let quarter_turn = ctx.constant(std::f32::consts::FRAC_PI_2.into());
+  let shifted = ctx.add(value, quarter_turn).unwrap();
+  nso_sin(shifted, extent + std::f32::consts::FRAC_PI_2, ctx)
+}
+
+/// Computes `sqrt(1 + (rate * |components|)^2)`, the factor by which a
+/// deformation that turns `rate` radians per unit length stretches space at
+/// distance `|components|` from its axis.
+fn nso_deformation_stretch(
+  rate: f32,
+  components: &[Node],
+  ctx: &mut Context,
+) -> Node {
+  let radius = nso_length(components, ctx);
+  let rate = ctx.constant(rate.into());
+  let one = ctx.constant(1.0);
+  let turn = ctx.mul(radius, rate).unwrap();
+  let turn_sq = ctx.square(turn).unwrap();
+  let sum = ctx.add(turn_sq, one).unwrap();
+  ctx.sqrt(sum).unwrap()
+}
+
+/// Twists a node around the y axis, rotating each slice by `rate` radians per
+/// unit of height.
+pub fn nso_twist(shape: Node, rate: f32, ctx: &mut Context) -> Node {
+  let x = ctx.x();
+  let y = ctx.y();
+  let z = ctx.z();
+  let rate_node = ctx.constant(rate.into());
+  let angle = ctx.mul(y, rate_node).unwrap();
+  let extent = NSO_REPEAT_EXTENT * rate.abs();
+  let sin = nso_sin(angle, extent, ctx);
+  let cos = nso_cos(angle, extent, ctx);
+
+  // rotate each slice back by its angle
+  let x_cos = ctx.mul(x, cos).unwrap();
+  let x_sin = ctx.mul(x, sin).unwrap();
+  let z_cos = ctx.mul(z, cos).unwrap();
+  let z_sin = ctx.mul(z, sin).unwrap();
+  let new_x = ctx.add(x_cos, z_sin).unwrap();
+  let new_z = ctx.sub(z_cos, x_sin).unwrap();
+  ctx.remap_xyz(shape, [new_x, y, new_z]).unwrap()
+}
+
+/// Scales down a node twisted with `nso_twist` by how much the twist
+/// stretches space, so that it remains a bound on the distance to its
+/// surface.
+pub fn nso_twist_bound(shape: Node, rate: f32, ctx: &mut Context) -> Node {
+  let x = ctx.x();
+  let z = ctx.z();
+  let stretch = nso_deformation_stretch(rate, &[x, z], ctx);
+  ctx.div(shape, stretch).unwrap()
+}
+
+/// Bends a node in the xy plane, curving the x axis towards y by `rate`
+/// radians per unit of length.
+pub fn nso_bend(shape: Node, rate: f32, ctx: &mut Context) -> Node {
+  let x = ctx.x();
+  let y = ctx.y();
+  let z = ctx.z();
+  let rate_node = ctx.constant(rate.into());
+  let angle = ctx.mul(x, rate_node).unwrap();
+  let extent = NSO_REPEAT_EXTENT * rate.abs();
+  let sin = nso_sin(angle, extent, ctx);
+  let cos = nso_cos(angle, extent, ctx);
+
+  let x_cos = ctx.mul(x, cos).unwrap();
+  let x_sin = ctx.mul(x, sin).unwrap();
+  let y_cos = ctx.mul(y, cos).unwrap();
+  let y_sin = ctx.mul(y, sin).unwrap();
+  let new_x = ctx.sub(x_cos, y_sin).unwrap();
+  let new_y = ctx.add(x_sin, y_cos).unwrap();
+  ctx.remap_xyz(shape, [new_x, new_y, z]).unwrap()
+}
+
+/// Scales down a node bent with `nso_bend` by how much the bend stretches
+/// space, so that it remains a bound on the distance to its surface.
+pub fn nso_bend_bound(shape: Node, rate: f32, ctx: &mut Context) -> Node {
+  let x = ctx.x();
+  let y = ctx.y();
+  let stretch = nso_deformation_stretch(rate, &[x, y], ctx);
+  ctx.div(shape, stretch).unwrap()
+}
+
+/// The smallest scale a taper will shrink a shape to, to avoid dividing by
+/// zero where the taper would pinch the shape to a point.
+const NSO_TAPER_MIN_SCALE: f32 = 0.01;
+
+/// Computes `1 + rate * y`, the scale of a tapered slice, clamped to stay
+/// positive.
+fn nso_taper_scale(rate: f32, ctx: &mut Context) -> Node {
+  let y = ctx.y();
+  let rate = ctx.constant(rate.into());
+  let one = ctx.constant(1.0);
+  let min_scale = ctx.constant(NSO_TAPER_MIN_SCALE.into());
+  let scale = ctx.mul(y, rate).unwrap();
+  let scale = ctx.add(scale, one).unwrap();
+  ctx.max(scale, min_scale).unwrap()
+}
+
+/// Tapers a node along the y axis, scaling each slice in x and z by
+/// `1 + rate * y`.
+pub fn nso_taper(shape: Node, rate: f32, ctx: &mut Context) -> Node {
+  let x = ctx.x();
+  let y = ctx.y();
+  let z = ctx.z();
+  let scale = nso_taper_scale(rate, ctx);
+  let new_x = ctx.div(x, scale).unwrap();
+  let new_z = ctx.div(z, scale).unwrap();
+  ctx.remap_xyz(shape, [new_x, y, new_z]).unwrap()
+}
+
+/// Scales down a node tapered with `nso_taper` by how much the taper stretches
+/// space, so that it remains a bound on the distance to its surface.
+pub fn nso_taper_bound(shape: Node, rate: f32, ctx: &mut Context) -> Node {
+  let x = ctx.x();
+  let z = ctx.z();
+  let scale = nso_taper_scale(rate, ctx);
+  let local_x = ctx.div(x, scale).unwrap();
+  let local_z = ctx.div(z, scale).unwrap();
+  let stretch = nso_deformation_stretch(rate, &[local_x, local_z], ctx);
+
+  // slices scaled up have their distances stretched too, but slices scaled
+  // down are already bounds
+  let one = ctx.constant(1.0);
+  let shrink = ctx.min(scale, one).unwrap();
+  let shape = ctx.mul(shape, shrink).unwrap();
+  ctx.div(shape, stretch).unwrap()
+}
+
+/// Offsets the surface of a node outwards by `distance`, rounding off its
+/// edges. A negative distance shrinks it instead.
+pub fn nso_offset(shape: Node, distance: f32, ctx: &mut Context) -> Node {
+  let distance = ctx.constant(distance.into());
+  ctx.sub(shape, distance).unwrap()
+}
+
+/// Hollows out a node, leaving a wall of `thickness` just inside its surface.
+pub fn nso_shell(shape: Node, thickness: f32, ctx: &mut Context) -> Node {
+  let half_thickness = ctx.constant((thickness / 2.0).into());
+  let centered = ctx.add(shape, half_thickness).unwrap();
+  let centered = ctx.abs(centered).unwrap();
+  ctx.sub(centered, half_thickness).unwrap()
+}
+
+/// Elongates a node by splitting it at the origin and pulling the halves
+/// apart by `h` in each direction along each axis, filling the gap with the
+/// cross-section at the split.
+pub fn nso_elongate(shape: Node, h: [f32; 3], ctx: &mut Context) -> Node {
+  let coords = [ctx.x(), ctx.y(), ctx.z()];
+  let mut new_coords = coords;
+  for (i, coord) in coords.into_iter().enumerate() {
+    if h[i] <= 0.0 {
+      continue;
+    }
+    let max = ctx.constant(h[i].into());
+    let min = ctx.constant((-h[i]).into());
+    let clamped = ctx.min(coord, max).unwrap();
+    let clamped = ctx.max(clamped, min).unwrap();
+    new_coords[i] = ctx.sub(coord, clamped).unwrap();
+  }
+  ctx.remap_xyz(shape, new_coords).unwrap()
+}
+
+/// How far from the opposite lattice points, in cells, the lattice points
+/// found by `nso_lattice_points` stop being exact. `nso_noise` holds each
+/// point's value this close to it before interpolating towards the next.
+const NSO_LATTICE_SNAP: f32 = 0.05;
+
+/// How strongly the lattice hash of `nso_noise` modulates its phase. Larger
+/// values look more random but amplify rounding in the lattice coordinates.
+const NSO_NOISE_SCRAMBLE: f32 = 8.0;
+
+/// A small seeded generator (splitmix64) for picking noise lattice offsets
+/// and hash constants.
+struct NoiseRng(u64);
+
+impl NoiseRng {
+  fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+  }
+
+  /// A float in `[0, 1)`.
+  fn next_f32(&mut self) -> f32 {
+    (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+  }
+
+  /// A vector with each component in `[0, scale)`.
+  fn next_vec3(&mut self, scale: f32) -> glam::Vec3 {
+    glam::Vec3::new(self.next_f32(), self.next_f32(), self.next_f32()) * scale
+  }
+}
+
+/// The factor that brings the summed octaves of `nso_noise` into `[-1, 1]`.
+fn nso_noise_normalization(octaves: u32) -> f32 {
+  1.0 / (2.0 * (1.0 - 0.5_f32.powi(octaves.max(1) as i32)))
+}
+
+/// The two lattice points around a lattice coordinate `u` along one axis, the
+/// nearest even and the nearest odd integer, with the distance to the even
+/// one.
+///
+/// The graph has no `floor`, so the points are found from triangle waves:
+/// `nso_triangle_wave(u, 1.0, ..)` is the distance to the nearest even
+/// integer, signed and flipped every other even cell, and the wave a cell
+/// further on gives the flip as its sign. Taking that sign with a steep clamp
+/// keeps the graph continuous. The even point is only exact up to
+/// `NSO_LATTICE_SNAP` from the odd integers, and likewise the other way
+/// around.
+fn nso_lattice_points(u: Node, extent: f32, ctx: &mut Context) -> [Node; 3] {
+  let one = ctx.constant(1.0);
+  let before = ctx.sub(u, one).unwrap();
+  let after = ctx.add(u, one).unwrap();
+  let wave_before = nso_triangle_wave(before, 1.0, extent + 1.0, ctx);
+  let wave = nso_triangle_wave(u, 1.0, extent, ctx);
+  let wave_after = nso_triangle_wave(after, 1.0, extent + 1.0, ctx);
+
+  let snap = ctx.constant(NSO_LATTICE_SNAP.into());
+  let minus_one = ctx.constant(-1.0);
+  let sign = |wave: Node, ctx: &mut Context| {
+    let steep = ctx.div(wave, snap).unwrap();
+    let steep = ctx.min(steep, one).unwrap();
+    ctx.max(steep, minus_one).unwrap()
+  };
+  let from_even = sign(wave_after, ctx);
+  let from_even = ctx.mul(wave, from_even).unwrap();
+  let even = ctx.sub(u, from_even).unwrap();
+  let from_odd = sign(wave, ctx);
+  let from_odd = ctx.mul(wave_before, from_odd).unwrap();
+  let odd = ctx.sub(u, from_odd).unwrap();
+  let distance = ctx.abs(wave).unwrap();
+
+  [even, odd, distance]
+}
+
+/// The lattice points of `nso_lattice_points` with the weight of the even
+/// one, which is zero where the even point isn't exact, and likewise the
+/// other way around.
+fn nso_noise_lattice(u: Node, extent: f32, ctx: &mut Context) -> [Node; 3] {
+  let [even, odd, distance] = nso_lattice_points(u, extent, ctx);
+
+  // smoothstep from the even point to the odd one, holding each near its end
+  let one = ctx.constant(1.0);
+  let zero = ctx.constant(0.0);
+  let snap = ctx.constant(NSO_LATTICE_SNAP.into());
+  let t = ctx.sub(distance, snap).unwrap();
+  let span = ctx.constant((1.0 - 2.0 * NSO_LATTICE_SNAP).into());
+  let t = ctx.div(t, span).unwrap();
+  let t = ctx.max(t, zero).unwrap();
+  let t = ctx.min(t, one).unwrap();
+  let three = ctx.constant(3.0);
+  let two = ctx.constant(2.0);
+  let t_sq = ctx.square(t).unwrap();
+  let step = ctx.mul(t, two).unwrap();
+  let step = ctx.sub(three, step).unwrap();
+  let step = ctx.mul(step, t_sq).unwrap();
+  let even_weight = ctx.sub(one, step).unwrap();
+
+  [even, odd, even_weight]
+}
+
+/// The steepest slope of one octave of `nso_noise` with a frequency of one,
+/// before normalization. Along each axis the value moves between two lattice
+/// values at most `2` apart, at the smoothstep's steepest slope.
+fn nso_noise_slope() -> f32 {
+  3.0_f32.sqrt() * 2.0 * 1.5 / (1.0 - 2.0 * NSO_LATTICE_SNAP)
+}
+
+/// Computes seeded fractal noise in `[-1, 1]`, with `frequency` lattice cells
+/// per unit in the first octave and each further octave doubling the
+/// frequency and halving the amplitude.
+///
+/// Each octave is value noise: every integer lattice point gets a
+/// pseudo-random value in `[-1, 1]`, and the points between are smoothly
+/// interpolated from the eight around them. The lattice points come from
+/// `nso_noise_lattice`, and their values from hashing their coordinates with
+/// nested sines, so the noise is exact for coordinates within
+/// `NSO_REPEAT_EXTENT` of the origin.
+pub fn nso_noise(
+  seed: u32,
+  frequency: f32,
+  octaves: u32,
+  ctx: &mut Context,
+) -> Node {
+  let mut rng = NoiseRng(seed as u64);
+  let axes = [ctx.x(), ctx.y(), ctx.z()];
+
+  let mut sum = ctx.constant(0.0);
+  for octave in 0..octaves.max(1) {
+    let octave_amplitude = 0.5_f32.powi(octave as i32);
+    let octave_frequency = frequency * 2.0_f32.powi(octave as i32);
+    // offset each octave so their lattices don't line up at the origin
+    let offset = rng.next_vec3(64.0);
+    let phase_k = rng.next_vec3(std::f32::consts::TAU);
+    let scramble_k = rng.next_vec3(std::f32::consts::TAU);
+    let phase = rng.next_f32() * std::f32::consts::TAU;
+    let scramble_phase = rng.next_f32() * std::f32::consts::TAU;
+
+    // per axis, the even and odd lattice points' share of each hash phase
+    let extent = NSO_REPEAT_EXTENT * octave_frequency.abs() + 64.0;
+    let frequency_node = ctx.constant(octave_frequency.into());
+    let lattices = [0, 1, 2].map(|i| {
+      let u = ctx.mul(axes[i], frequency_node).unwrap();
+      let offset = ctx.constant(offset[i].into());
+      let u = ctx.add(u, offset).unwrap();
+      let [even, odd, even_weight] = nso_noise_lattice(u, extent, ctx);
+      let odd_weight = ctx.constant(1.0);
+      let odd_weight = ctx.sub(odd_weight, even_weight).unwrap();
+      let phase_k = ctx.constant(phase_k[i].into());
+      let scramble_k = ctx.constant(scramble_k[i].into());
+      [(even, even_weight), (odd, odd_weight)].map(|(point, weight)| {
+        let phase = ctx.mul(point, phase_k).unwrap();
+        let scramble = ctx.mul(point, scramble_k).unwrap();
+        (phase, scramble, weight)
+      })
+    });
+
+    // the phases reach at most the length of their constants times the
+    // furthest lattice point, plus the added phases
+    let reach = (extent + 1.0) * 3.0_f32.sqrt();
+    let scramble_extent = scramble_k.length() * reach + std::f32::consts::TAU;
+    let phase_extent =
+      phase_k.length() * reach + std::f32::consts::TAU + NSO_NOISE_SCRAMBLE;
+
+    let phase = ctx.constant(phase.into());
+    let scramble_phase = ctx.constant(scramble_phase.into());
+    let scramble_amount = ctx.constant(NSO_NOISE_SCRAMBLE.into());
+    let octave_weight = ctx.constant(octave_amplitude.into());
+    for corner in 0..8 {
+      let [x, y, z] = [0, 1, 2].map(|i| lattices[i][(corner >> i) & 1]);
+      let corner_phase = ctx.add(x.0, y.0).unwrap();
+      let corner_phase = ctx.add(corner_phase, z.0).unwrap();
+      let corner_phase = ctx.add(corner_phase, phase).unwrap();
+      let scramble = ctx.add(x.1, y.1).unwrap();
+      let scramble = ctx.add(scramble, z.1).unwrap();
+      let scramble = ctx.add(scramble, scramble_phase).unwrap();
+      let scramble = nso_sin(scramble, scramble_extent, ctx);
+      let scramble = ctx.mul(scramble, scramble_amount).unwrap();
+      let corner_phase = ctx.add(corner_phase, scramble).unwrap();
+      let value = nso_sin(corner_phase, phase_extent, ctx);
+
+      let weight = ctx.mul(x.2, y.2).unwrap();
+      let weight = ctx.mul(weight, z.2).unwrap();
+      let weight = ctx.mul(weight, octave_weight).unwrap();
+      let value = ctx.mul(value, weight).unwrap();
+      sum = ctx.add(sum, value).unwrap();
+    }
+  }
+
+  let normalization = ctx.constant(nso_noise_normalization(octaves).into());
+  ctx.mul(sum, normalization).unwrap()
+}
+
+/// Displaces the surface of a node by `nso_noise` scaled by `amplitude`, and
+/// scales the result down by the steepest slope the noise can add so that it
+/// remains a bound on the distance to the surface.
+pub fn nso_displace(
+  shape: Node,
+  seed: u32,
+  frequency: f32,
+  amplitude: f32,
+  octaves: u32,
+  ctx: &mut Context,
+) -> Node {
+  let octaves = octaves.max(1);
+  let noise = nso_noise(seed, frequency, octaves, ctx);
+  let amplitude_node = ctx.constant(amplitude.into());
+  let noise = ctx.mul(noise, amplitude_node).unwrap();
+  let displaced = ctx.add(shape, noise).unwrap();
+
+  // each octave's slope is at most its amplitude times its frequency times
+  // the slope of a unit octave, and those products are all equal
+  let slope = amplitude.abs()
+    * nso_noise_normalization(octaves)
+    * frequency.abs()
+    * nso_noise_slope()
+    * octaves as f32;
+  let stretch = ctx.constant((1.0 + slope).into());
+  ctx.div(displaced, stretch).unwrap()
+}
+
+/// Mirrors a node across the planes through `offset` that are perpendicular
+/// to each enabled axis. The side of each plane facing the positive axis is
+/// kept and reflected onto the other side.
+pub fn nso_mirror(
+  shape: Node,
+  axes: [bool; 3],
+  offset: [f32; 3],
+  ctx: &mut Context,
+) -> Node {
+  let coords = [ctx.x(), ctx.y(), ctx.z()];
+  let mut new_coords = coords;
+  for (i, coord) in coords.into_iter().enumerate() {
+    if !axes[i] {
+      continue;
+    }
+    let offset = ctx.constant(offset[i].into());
+    let moved = ctx.sub(coord, offset).unwrap();
+    let folded = ctx.abs(moved).unwrap();
+    new_coords[i] = ctx.add(folded, offset).unwrap();
+  }
+  ctx.remap_xyz(shape, new_coords).unwrap()
+}
+
+/// Repeats a node `count` times around `axis`, by folding space into a wedge
+/// of angle `π / count` either side of the first axis perpendicular to `axis`
+/// in xyz order. Each copy is also mirrored across its own center line, so
+/// shapes should be symmetric across it.
+pub fn nso_radial_symmetry(
+  shape: Node,
+  axis: Axis,
+  count: u32,
+  ctx: &mut Context,
+) -> Node {
+  if count < 2 {
+    return shape;
+  }
+  let coords = [ctx.x(), ctx.y(), ctx.z()];
+  let (u_index, v_index) = axis.perpendicular();
+  let mut u = coords[u_index];
+  let mut v = coords[v_index];
+
+  // the wedge is bounded by the u axis and the line at `half_angle`; reflect
+  // across each in turn, which rotates points towards the wedge by twice
+  // `half_angle` per iteration
+  let half_angle = std::f32::consts::PI / count as f32;
+  let (sin, cos) = half_angle.sin_cos();
+  let normal_u = ctx.constant((-sin).into());
+  let normal_v = ctx.constant(cos.into());
+  let zero = ctx.constant(0.0);
+  let two = ctx.constant(2.0);
+  for _ in 0..(count / 2 + 1) {
+    v = ctx.abs(v).unwrap();
+    let along_u = ctx.mul(u, normal_u).unwrap();
+    let along_v = ctx.mul(v, normal_v).unwrap();
+    let dot = ctx.add(along_u, along_v).unwrap();
+    let dot = ctx.max(dot, zero).unwrap();
+    let dot = ctx.mul(dot, two).unwrap();
+    let offset_u = ctx.mul(dot, normal_u).unwrap();
+    let offset_v = ctx.mul(dot, normal_v).unwrap();
+    u = ctx.sub(u, offset_u).unwrap();
+    v = ctx.sub(v, offset_v).unwrap();
+  }
+  v = ctx.abs(v).unwrap();
+
+  let mut new_coords = coords;
+  new_coords[u_index] = u;
+  new_coords[v_index] = v;
+  ctx.remap_xyz(shape, new_coords).unwrap()
+}
+
+/// Transform volume of size `size` centered at `pos` to a unit cube. The
+/// volume reaches `size` from `pos` along each axis, so a point `p` in the
+/// cube samples the node at `p * size + pos`, matching `FullMesh::denormalize`.
 pub fn nso_normalize_region(
   shape: Node,
   pos: [f32; 3],
@@ -72,12 +945,12 @@ pub fn nso_normalize_region(
   let size_x = ctx.constant(size[0].into());
   let size_y = ctx.constant(size[1].into());
   let size_z = ctx.constant(size[2].into());
-  let moved_x = ctx.add(x, pos_x).unwrap();
-  let moved_y = ctx.add(y, pos_y).unwrap();
-  let moved_z = ctx.add(z, pos_z).unwrap();
-  let new_x = ctx.mul(moved_x, size_x).unwrap();
-  let new_y = ctx.mul(moved_y, size_y).unwrap();
-  let new_z = ctx.mul(moved_z, size_z).unwrap();
+  let scaled_x = ctx.mul(x, size_x).unwrap();
+  let scaled_y = ctx.mul(y, size_y).unwrap();
+  let scaled_z = ctx.mul(z, size_z).unwrap();
+  let new_x = ctx.add(scaled_x, pos_x).unwrap();
+  let new_y = ctx.add(scaled_y, pos_y).unwrap();
+  let new_z = ctx.add(scaled_z, pos_z).unwrap();
   ctx.remap_xyz(shape, [new_x, new_y, new_z]).unwrap()
 }
 
@@ -98,13 +971,13 @@ pub fn nso_denormalize_region(
   let size_x = ctx.constant(size[0].into());
   let size_y = ctx.constant(size[1].into());
   let size_z = ctx.constant(size[2].into());
-  let new_x = ctx.div(x, size_x).unwrap();
-  let new_y = ctx.div(y, size_y).unwrap();
-  let new_z = ctx.div(z, size_z).unwrap();
-  let moved_x = ctx.sub(new_x, pos_x).unwrap();
-  let moved_y = ctx.sub(new_y, pos_y).unwrap();
-  let moved_z = ctx.sub(new_z, pos_z).unwrap();
-  ctx.remap_xyz(shape, [moved_x, moved_y, moved_z]).unwrap()
+  let moved_x = ctx.sub(x, pos_x).unwrap();
+  let moved_y = ctx.sub(y, pos_y).unwrap();
+  let moved_z = ctx.sub(z, pos_z).unwrap();
+  let new_x = ctx.div(moved_x, size_x).unwrap();
+  let new_y = ctx.div(moved_y, size_y).unwrap();
+  let new_z = ctx.div(moved_z, size_z).unwrap();
+  ctx.remap_xyz(shape, [new_x, new_y, new_z]).unwrap()
 }
 
 /// Clamps a node to the range [-1, 1], and drastically steepens the slope of
@@ -118,41 +991,418 @@ pub fn nso_clamp(shape: Node, ctx: &mut Context) -> Node {
   ctx.max(outside_bounded, neg_one).unwrap()
 }
 
-/// Clamps and scales a node by the given factor.
-pub fn nso_bleed(shape: Node, factor: f32, ctx: &mut Context) -> Node {
-  let shape = nso_clamp(shape, ctx);
-  let factor = ctx.constant(factor.into());
-  let x = ctx.x();
-  let new_x = ctx.div(x, factor).unwrap();
-  let y = ctx.y();
-  let new_y = ctx.div(y, factor).unwrap();
-  let z = ctx.z();
-  let new_z = ctx.div(z, factor).unwrap();
-  ctx.remap_xyz(shape, [new_x, new_y, new_z]).unwrap()
+/// Creates the color fields (one per channel, in `[0, 1]`) of a solid rgb
+/// color.
+pub fn nso_color(rgb: [u8; 3], ctx: &mut Context) -> [Node; 3] {
+  rgb.map(|channel| ctx.constant((channel as f64) / 255.0))
 }
 
-/// Color a node with the given rgb value. It is recommended to use this on a
-/// node that has had a "bleed" applied to it to reduce the chances of vertices
-/// being clipped.
-pub fn nso_color(shape: Node, rgb: [u8; 3], ctx: &mut Context) -> Node {
-  let bitshifted_color =
-    rgb[0] as u32 * 256 * 256 + rgb[1] as u32 * 256 + rgb[2] as u32;
-  let float_cast_color = bitshifted_color as f32 / (256_u32).pow(3) as f32;
-  let color_val = float_cast_color * 0.9 + 0.1;
-  let color_val = ctx.constant(color_val.into());
+/// Interpolates between two sets of color fields channel by channel, giving
+/// `a` where `t` is `1` and `b` where `t` is `0`.
+pub fn nso_mix_color(
+  a: [Node; 3],
+  b: [Node; 3],
+  t: Node,
+  ctx: &mut Context,
+) -> [Node; 3] {
+  [0, 1, 2].map(|i| nso_mix(a[i], b[i], t, ctx))
+}
 
-  // convert from -1 inside and 1 outside to 1 inside and 0 outside
-  let neg_point_five = ctx.constant(-0.5);
+/// Steps a node sharply from `0` where it's negative to `1` where it's
+/// positive, across a very thin band around zero.
+pub fn nso_step(value: Node, ctx: &mut Context) -> Node {
+  let steep_slope = ctx.constant(1000.0);
+  let half = ctx.constant(0.5);
+  let zero = ctx.constant(0.0);
   let one = ctx.constant(1.0);
-  let shape = ctx.sub(shape, one).unwrap();
-  let shape = ctx.mul(shape, neg_point_five).unwrap();
+  let t = ctx.mul(value, steep_slope).unwrap();
+  let t = ctx.add(t, half).unwrap();
+  let t = ctx.max(t, zero).unwrap();
+  ctx.min(t, one).unwrap()
+}
 
-  // clamp to 0-1
+/// Computes the weight of a linear gradient along `axis`, rising from `0` at
+/// `start` to `1` at `end` and clamped beyond them. If `start` and `end` are
+/// equal the weight steps sharply between them.
+pub fn nso_linear_gradient(
+  axis: Axis,
+  start: f32,
+  end: f32,
+  ctx: &mut Context,
+) -> Node {
+  let coord = [ctx.x(), ctx.y(), ctx.z()][axis.index()];
+  let start_node = ctx.constant(start.into());
+  let offset = ctx.sub(coord, start_node).unwrap();
+  if start == end {
+    return nso_step(offset, ctx);
+  }
+  let inv_span = ctx.constant((1.0 / (end - start)).into());
+  let t = ctx.mul(offset, inv_span).unwrap();
   let zero = ctx.constant(0.0);
-  let shape = ctx.max(shape, zero).unwrap();
   let one = ctx.constant(1.0);
-  let shape = ctx.min(shape, one).unwrap();
+  let t = ctx.max(t, zero).unwrap();
+  ctx.min(t, one).unwrap()
+}
+
+/// Computes the weight of a radial gradient, rising from `0` at the origin to
+/// `1` at `radius` and clamped beyond it.
+pub fn nso_radial_gradient(radius: f32, ctx: &mut Context) -> Node {
+  if radius <= 0.0 {
+    return ctx.constant(1.0);
+  }
+  let axes = [ctx.x(), ctx.y(), ctx.z()];
+  let length = nso_length(&axes, ctx);
+  let inv_radius = ctx.constant((1.0 / radius).into());
+  let t = ctx.mul(length, inv_radius).unwrap();
+  let one = ctx.constant(1.0);
+  ctx.min(t, one).unwrap()
+}
+
+/// Computes a signed square wave along a node, `1` and `-1` in alternating
+/// bands of `width` with the first positive band starting at zero.
+fn nso_square_wave(value: Node, width: f32, ctx: &mut Context) -> Node {
+  let wave = nso_triangle_wave(value, width / 2.0, NSO_REPEAT_EXTENT, ctx);
+  let step = nso_step(wave, ctx);
+  let two = ctx.constant(2.0);
+  let one = ctx.constant(1.0);
+  let wave = ctx.mul(step, two).unwrap();
+  ctx.sub(wave, one).unwrap()
+}
+
+/// Computes the weight of stripes across `axis`: bands of `width` that
+/// alternate between `0` and `1`, with a `0` band starting at the origin. A
+/// width of zero gives no stripes.
+pub fn nso_stripes(axis: Axis, width: f32, ctx: &mut Context) -> Node {
+  if width <= 0.0 {
+    return ctx.constant(0.0);
+  }
+  let coord = [ctx.x(), ctx.y(), ctx.z()][axis.index()];
+  let wave = nso_square_wave(coord, width, ctx);
+  let neg_half = ctx.constant(-0.5);
+  let half = ctx.constant(0.5);
+  let t = ctx.mul(wave, neg_half).unwrap();
+  ctx.add(t, half).unwrap()
+}
+
+/// Computes the weight of a 3D checkerboard of cubes with edge `size`,
+/// alternating between `0` and `1`, with a `0` cube at the positive corner of
+/// the origin. A size of zero gives no checkerboard.
+pub fn nso_checker(size: f32, ctx: &mut Context) -> Node {
+  if size <= 0.0 {
+    return ctx.constant(0.0);
+  }
+  // the product of the square waves flips sign with each cube crossed
+  let axes = [ctx.x(), ctx.y(), ctx.z()];
+  let waves = axes.map(|axis| nso_square_wave(axis, size, ctx));
+  let product = ctx.mul(waves[0], waves[1]).unwrap();
+  let product = ctx.mul(product, waves[2]).unwrap();
+  let neg_half = ctx.constant(-0.5);
+  let half = ctx.constant(0.5);
+  let t = ctx.mul(product, neg_half).unwrap();
+  ctx.add(t, half).unwrap()
+}
+
+/// Computes the weight of a noise tint: `nso_noise` remapped into `[0, 1]`
+/// and scaled by `strength`.
+pub fn nso_noise_tint(
+  seed: u32,
+  frequency: f32,
+  octaves: u32,
+  strength: f32,
+  ctx: &mut Context,
+) -> Node {
+  let noise = nso_noise(seed, frequency, octaves, ctx);
+  let half = ctx.constant(0.5);
+  let zero = ctx.constant(0.0);
+  let one = ctx.constant(1.0);
+  let t = ctx.mul(noise, half).unwrap();
+  let t = ctx.add(t, half).unwrap();
+  let t = ctx.max(t, zero).unwrap();
+  let t = ctx.min(t, one).unwrap();
+  let strength = ctx.constant(strength.clamp(0.0, 1.0).into());
+  ctx.mul(t, strength).unwrap()
+}
+
+/// Interpolates between two solid rgb colors, giving the first where `t` is
+/// `0` and the second where `t` is `1`.
+pub fn nso_color_ramp(
+  colors: [[u8; 3]; 2],
+  t: Node,
+  ctx: &mut Context,
+) -> [Node; 3] {
+  let first = nso_color(colors[0], ctx);
+  let second = nso_color(colors[1], ctx);
+  nso_mix_color(second, first, t, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn unit_sphere(ctx: &mut Context) -> Node {
+    let x = ctx.x();
+    let y = ctx.y();
+    let z = ctx.z();
+    let x_sq = ctx.square(x).unwrap();
+    let y_sq = ctx.square(y).unwrap();
+    let z_sq = ctx.square(z).unwrap();
+    let sum = ctx.add(x_sq, y_sq).unwrap();
+    let sum = ctx.add(sum, z_sq).unwrap();
+    let sum = ctx.sqrt(sum).unwrap();
+    let one = ctx.constant(1.0);
+    ctx.sub(sum, one).unwrap()
+  }
+
+  #[test]
+  fn test_matrix_transform_moves_shape() {
+    let mut ctx = Context::new();
+    let sphere = unit_sphere(&mut ctx);
+    let matrix = glam::Mat4::from_scale_rotation_translation(
+      glam::Vec3::new(2.0, 1.0, 1.0),
+      glam::Quat::IDENTITY,
+      glam::Vec3::new(3.0, 0.0, 0.0),
+    )
+    .to_cols_array();
+    let moved = nso_matrix_transform(sphere, matrix, &mut ctx).unwrap();
+
+    let centre = ctx.eval_xyz(moved, 3.0, 0.0, 0.0).unwrap();
+    let stretched_edge = ctx.eval_xyz(moved, 5.0, 0.0, 0.0).unwrap();
+    assert!((centre + 1.0).abs() < 1e-6);
+    assert!(stretched_edge.abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_rotate_quarter_turn() {
+    let mut ctx = Context::new();
+    let sphere = unit_sphere(&mut ctx);
+    let moved = nso_translate(sphere, [2.0, 0.0, 0.0], &mut ctx);
+    let rotation = glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+    let rotated = nso_rotate(moved, rotation, &mut ctx);
+
+    let centre = ctx.eval_xyz(rotated, 0.0, 2.0, 0.0).unwrap();
+    assert!((centre + 1.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_smooth_union_reduces_to_union() {
+    let mut ctx = Context::new();
+    let a = unit_sphere(&mut ctx);
+    let b = nso_translate(a, [1.5, 0.0, 0.0], &mut ctx);
+    let hard = nso_union(a, b, &mut ctx);
+    let smooth = nso_smooth_union(a, b, 0.5, &mut ctx);
+
+    // far from the seam the blend has no effect
+    let hard_far = ctx.eval_xyz(hard, -0.5, 0.0, 0.0).unwrap();
+    let smooth_far = ctx.eval_xyz(smooth, -0.5, 0.0, 0.0).unwrap();
+    assert!((hard_far - smooth_far).abs() < 1e-6);
+    // at the seam the fillet adds material
+    let hard_seam = ctx.eval_xyz(hard, 0.75, 0.9, 0.0).unwrap();
+    let smooth_seam = ctx.eval_xyz(smooth, 0.75, 0.9, 0.0).unwrap();
+    assert!(smooth_seam < hard_seam);
+  }
+
+  #[test]
+  fn test_triangle_wave() {
+    let mut ctx = Context::new();
+    let x = ctx.x();
+    let wave = nso_triangle_wave(x, 1.0, 100.0, &mut ctx);
+    for (input, expected) in [
+      (0.5, 0.5),
+      (1.5, 0.5),
+      (-2.5, 0.5),
+      (4.25, 0.25),
+      (98.0, -0.0),
+    ] {
+      let value = ctx.eval_xyz(wave, input, 0.0, 0.0).unwrap();
+      assert!((value - expected).abs() < 1e-4, "wave({input}) = {value}");
+    }
+  }
+
+  #[test]
+  fn test_sin_cos() {
+    let mut ctx = Context::new();
+    let x = ctx.x();
+    let sin = nso_sin(x, 100.0, &mut ctx);
+    let cos = nso_cos(x, 100.0, &mut ctx);
+    for i in -40..40 {
+      let input = i as f64 * 2.3;
+      let sin_value = ctx.eval_xyz(sin, input, 0.0, 0.0).unwrap();
+      let cos_value = ctx.eval_xyz(cos, input, 0.0, 0.0).unwrap();
+      assert!((sin_value - input.sin()).abs() < 1e-5, "sin({input})");
+      assert!((cos_value - input.cos()).abs() < 1e-5, "cos({input})");
+    }
+  }
+
+  #[test]
+  fn test_twist() {
+    let mut ctx = Context::new();
+    let sphere = unit_sphere(&mut ctx);
+    let sphere = nso_translate(sphere, [2.0, 0.0, 0.0], &mut ctx);
+    let twisted = nso_twist(sphere, std::f32::consts::PI, &mut ctx);
+
+    // a quarter turn by half a unit of height carries the sphere from x to z
+    let at_base = ctx.eval_xyz(twisted, 2.0, 0.0, 0.0).unwrap();
+    let turned = ctx.eval_xyz(twisted, 0.0, 0.5, 2.0).unwrap();
+    let unturned = ctx.eval_xyz(twisted, 2.0, 0.5, 0.0).unwrap();
+    assert!((at_base + 1.0).abs() < 1e-5);
+    assert!(turned < 0.0);
+    assert!(unturned > 0.0);
+  }
+
+  #[test]
+  fn test_noise_is_seeded() {
+    let mut ctx = Context::new();
+    let a = nso_noise(7, 0.5, 3, &mut ctx);
+    let b = nso_noise(7, 0.5, 3, &mut ctx);
+    let c = nso_noise(8, 0.5, 3, &mut ctx);
+    let mut differs = false;
+    for i in 0..20 {
+      let p = i as f64 * 0.37;
+      let a = ctx.eval_xyz(a, p, -p, 0.5 * p).unwrap();
+      let b = ctx.eval_xyz(b, p, -p, 0.5 * p).unwrap();
+      let c = ctx.eval_xyz(c, p, -p, 0.5 * p).unwrap();
+      assert_eq!(a, b);
+      assert!(a.abs() <= 1.0);
+      differs |= a != c;
+    }
+    assert!(differs);
+  }
+
+  #[test]
+  fn test_noise_is_continuous() {
+    // stepping across many lattice cells, the noise never jumps by more than
+    // its slope allows, including where the lattice points switch over
+    let mut ctx = Context::new();
+    let noise = nso_noise(3, 1.0, 1, &mut ctx);
+    let step = 0.01;
+    let max_change = nso_noise_normalization(1) * nso_noise_slope() * step;
+    let mut previous = ctx.eval_xyz(noise, 0.0, 0.3, -0.2).unwrap();
+    let (mut min, mut max) = (previous, previous);
+    for i in 1..1000 {
+      let p = i as f64 * step as f64;
+      let value = ctx
+        .eval_xyz(noise, p, 0.3 + 0.5 * p, -0.2 - 0.25 * p)
+        .unwrap();
+      assert!((value - previous).abs() <= max_change as f64 * 1.01);
+      previous = value;
+      min = min.min(value);
+      max = max.max(value);
+    }
+    // and it isn't a flat or barely varying field
+    assert!(max - min > 0.5);
+  }
+
+  #[test]
+  fn test_normalize_region() {
+    let mut ctx = Context::new();
+    let sphere = unit_sphere(&mut ctx);
+    let sphere = nso_translate(sphere, [2.0, 0.0, 0.0], &mut ctx);
+    let normalized =
+      nso_normalize_region(sphere, [2.0, 0.0, 0.0], [4.0, 4.0, 4.0], &mut ctx);
+    // the sphere's surface lies a quarter of the way to the cube's face
+    let d = ctx.eval_xyz(normalized, 0.25, 0.0, 0.0).unwrap();
+    assert!(d.abs() < 1e-5);
+
+    let restored = nso_denormalize_region(
+      normalized,
+      [2.0, 0.0, 0.0],
+      [4.0, 4.0, 4.0],
+      &mut ctx,
+    );
+    let d = ctx.eval_xyz(restored, 3.0, 0.0, 0.0).unwrap();
+    assert!(d.abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_color_patterns() {
+    let mut ctx = Context::new();
+    let stripes = nso_stripes(Axis::X, 0.5, &mut ctx);
+    assert_eq!(ctx.eval_xyz(stripes, 0.25, 0.0, 0.0).unwrap(), 0.0);
+    assert_eq!(ctx.eval_xyz(stripes, 0.75, 0.0, 0.0).unwrap(), 1.0);
+    assert_eq!(ctx.eval_xyz(stripes, -0.25, 0.0, 0.0).unwrap(), 1.0);
+
+    let checker = nso_checker(1.0, &mut ctx);
+    assert_eq!(ctx.eval_xyz(checker, 0.5, 0.5, 0.5).unwrap(), 0.0);
+    assert_eq!(ctx.eval_xyz(checker, 1.5, 0.5, 0.5).unwrap(), 1.0);
+    assert_eq!(ctx.eval_xyz(checker, -0.5, -0.5, 0.5).unwrap(), 0.0);
+
+    let gradient = nso_linear_gradient(Axis::Y, -1.0, 1.0, &mut ctx);
+    assert_eq!(ctx.eval_xyz(gradient, 0.0, 0.5, 0.0).unwrap(), 0.75);
+    assert_eq!(ctx.eval_xyz(gradient, 0.0, 3.0, 0.0).unwrap(), 1.0);
+  }
+
+  #[test]
+  fn test_repeat_finite() {
+    let mut ctx = Context::new();
+    let sphere = unit_sphere(&mut ctx);
+    let sphere = nso_translate(sphere, [0.5, 0.0, 0.0], &mut ctx);
+    let repeated =
+      nso_repeat_finite(sphere, [3.0, 0.0, 0.0], [2, 1, 1], &mut ctx);
+
+    // two copies in cells centered on -1.5 and 1.5
+    let left = ctx.eval_xyz(repeated, -1.0, 0.0, 0.0).unwrap();
+    let right = ctx.eval_xyz(repeated, 2.0, 0.0, 0.0).unwrap();
+    let between = ctx.eval_xyz(repeated, 0.5, 0.0, 0.0).unwrap();
+    let beyond = ctx.eval_xyz(repeated, 4.5, 0.0, 0.0).unwrap();
+    let before = ctx.eval_xyz(repeated, -5.0, 0.0, 0.0).unwrap();
+    assert!((left + 1.0).abs() < 1e-5);
+    assert!((right + 1.0).abs() < 1e-5);
+    assert!((between - 0.5).abs() < 1e-5);
+    assert!((beyond - 1.5).abs() < 1e-5);
+    assert!((before - 3.0).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_repeat_is_unmirrored() {
+    let mut ctx = Context::new();
+    let half = ctx.constant(0.5);
+    let sides = [ctx.x(), ctx.y(), ctx.z()].map(|axis| {
+      let axis = ctx.abs(axis).unwrap();
+      ctx.sub(axis, half).unwrap()
+    });
+    let cube = ctx.max(sides[0], sides[1]).unwrap();
+    let cube = ctx.max(cube, sides[2]).unwrap();
+    let cube = nso_translate(cube, [0.7, 0.0, 0.0], &mut ctx);
+    let repeated = nso_repeat(cube, [3.0, 0.0, 0.0], &mut ctx);
+
+    let inside = ctx.eval_xyz(repeated, 0.7, 0.2, 0.0).unwrap();
+    let mirrored = ctx.eval_xyz(repeated, -0.7, 0.2, 0.0).unwrap();
+    assert!((inside + 0.3).abs() < 1e-5);
+    assert!((mirrored - 0.9).abs() < 1e-5);
+    for i in 0..60 {
+      let x = -1.5 + i as f64 * 0.05;
+      let y = 0.3 - i as f64 * 0.01;
+      let expected = ctx.eval_xyz(repeated, x, y, 0.0).unwrap();
+      for copy in [-2.0, -1.0, 1.0, 5.0] {
+        let value = ctx.eval_xyz(repeated, x + copy * 3.0, y, 0.0).unwrap();
+        assert!((value - expected).abs() < 1e-4, "{x} {copy}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_radial_symmetry() {
+    for count in [2, 3, 5, 12] {
+      let mut ctx = Context::new();
+      let sphere = unit_sphere(&mut ctx);
+      let sphere = nso_translate(sphere, [3.0, 0.0, 0.0], &mut ctx);
+      let ring = nso_radial_symmetry(sphere, Axis::Y, count, &mut ctx);
+
+      let step = std::f64::consts::TAU / count as f64;
+      for i in 0..count {
+        let (sin, cos) = (i as f64 * step).sin_cos();
+        let centre = ctx.eval_xyz(ring, 3.0 * cos, 0.0, 3.0 * sin).unwrap();
+        assert!((centre + 1.0).abs() < 1e-4, "copy {i} of {count}");
+      }
+    }
+  }
 
-  // multiply by rgb
-  ctx.mul(shape, color_val).unwrap()
+  #[test]
+  fn test_matrix_transform_rejects_singular() {
+    let mut ctx = Context::new();
+    let sphere = unit_sphere(&mut ctx);
+    let matrix =
+      glam::Mat4::from_scale(glam::Vec3::new(1.0, 0.0, 1.0)).to_cols_array();
+    assert!(nso_matrix_transform(sphere, matrix, &mut ctx).is_none());
+  }
 }
@@ -0,0 +1,158 @@
+//! The binary `.pls` asset format.
+//!
+//! A `.pls` file starts with the magic bytes `PLS\0` and a little-endian `u32`
+//! format version, followed by the bincode-encoded asset: the rhai script the
+//! shapes came from (if any), the evaluated composition, and any meshes baked
+//! from it. Games can load the baked meshes directly, while the editor can use
+//! the script to keep editing.
+
+use std::{
+  fs::File,
+  io::{BufReader, BufWriter, Read, Write},
+  path::Path,
+};
+
+use anyhow::{Error, Result};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{comp::Composition, lod::Lod};
+
+/// The bytes every `.pls` file starts with.
+pub const PLS_MAGIC: [u8; 4] = *b"PLS\0";
+
+/// The version of the `.pls` format. This is bumped whenever the encoding of
+/// the asset changes incompatibly.
+pub const PLS_VERSION: u32 = 1;
+
+/// The largest asset a `.pls` file is read as, in bytes, so that a corrupted
+/// length can't make reading allocate without bound.
+pub const PLS_SIZE_LIMIT: u64 = 1 << 30;
+
+/// The bincode options the asset is encoded with.
+fn bincode_options() -> impl Options {
+  bincode::DefaultOptions::new()
+    .with_fixint_encoding()
+    .allow_trailing_bytes()
+    .with_limit(PLS_SIZE_LIMIT)
+}
+
+/// A mesh baked from an asset's composition at one level of detail.
+pub type PlsLod = Lod;
+
+/// The contents of a `.pls` file.
+#[derive(Serialize, Deserialize)]
+pub struct PlsAsset {
+  /// The rhai script the composition was evaluated from, if it's kept.
+  pub script:      Option<String>,
+  pub composition: Composition,
+  /// Baked meshes, from the most to the least detailed.
+  pub lods:        Vec<PlsLod>,
+}
+
+impl PlsAsset {
+  pub fn new(composition: Composition) -> Self {
+    PlsAsset {
+      script: None,
+      composition,
+      lods: Vec::new(),
+    }
+  }
+
+  /// Writes the asset in the `.pls` format.
+  pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+    writer.write_all(&PLS_MAGIC)?;
+    writer.write_all(&PLS_VERSION.to_le_bytes())?;
+    bincode_options().serialize_into(&mut writer, self)?;
+    writer.flush()?;
+    Ok(())
+  }
+
+  /// Reads an asset in the `.pls` format, checking its magic bytes and
+  /// version. Assets larger than `PLS_SIZE_LIMIT` are refused.
+  pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != PLS_MAGIC {
+      return Err(Error::msg("not a .pls file"));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != PLS_VERSION {
+      return Err(Error::msg(format!(
+        "unsupported .pls version {}, expected {}",
+        version, PLS_VERSION
+      )));
+    }
+    Ok(bincode_options().deserialize_from(reader)?)
+  }
+
+  /// Writes the asset to a `.pls` file.
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+    self.write(BufWriter::new(File::create(path)?))
+  }
+
+  /// Reads an asset from a `.pls` file.
+  pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+    Self::read(BufReader::new(File::open(path)?))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{builder::sphere, mesh::FullMesh};
+
+  fn asset() -> PlsAsset {
+    let mut composition = Composition::new();
+    composition.add_shape(sphere(1.0), [0.0, 0.0, 0.0]);
+    let mut asset = PlsAsset::new(composition);
+    asset.script = Some("[shape(sphere(1.0), [0.0, 0.0, 0.0])]".to_string());
+    asset.lods.push(PlsLod {
+      max_depth: 4,
+      mesh:      FullMesh {
+        vertices:     vec![glam::Vec3A::X, glam::Vec3A::Y, glam::Vec3A::Z],
+        triangles:    vec![glam::UVec3::new(0, 1, 2)],
+        normals:      None,
+        colors:       None,
+        material_ids: Some(vec![0, 0, 1]),
+      },
+    });
+    asset
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let mut bytes = Vec::new();
+    asset().write(&mut bytes).unwrap();
+    assert_eq!(bytes[..4], PLS_MAGIC);
+
+    let read = PlsAsset::read(bytes.as_slice()).unwrap();
+    assert_eq!(read.script, asset().script);
+    assert_eq!(read.lods[0].max_depth, 4);
+    assert_eq!(read.lods[0].mesh.vertices, asset().lods[0].mesh.vertices);
+    assert_eq!(read.lods[0].mesh.material_ids, Some(vec![0, 0, 1]));
+  }
+
+  #[test]
+  fn test_rejects_other_versions() {
+    let mut bytes = Vec::new();
+    asset().write(&mut bytes).unwrap();
+    bytes[4..8].copy_from_slice(&(PLS_VERSION + 1).to_le_bytes());
+    assert!(PlsAsset::read(bytes.as_slice()).is_err());
+    assert!(PlsAsset::read(&b"OBJ\0"[..]).is_err());
+  }
+
+  #[test]
+  fn test_rejects_damaged_files() {
+    let mut bytes = Vec::new();
+    asset().write(&mut bytes).unwrap();
+    assert!(PlsAsset::read(&bytes[..bytes.len() - 5]).is_err());
+
+    // a length far past the limit is refused before anything is allocated
+    // for it; the script's length follows the header and its `Some` tag
+    bytes[9..17].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(PlsAsset::read(bytes.as_slice()).is_err());
+  }
+}
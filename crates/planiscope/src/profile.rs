@@ -0,0 +1,330 @@
+use anyhow::{Error, Result};
+use fidget::{context::Node, Context};
+
+use crate::{comp::CompilationSettings, nso::*};
+
+/// A 2D profile, defined in a plane with coordinates `u` and `v`. Profiles are
+/// lifted into 3D shapes by `ShapeDef::Extrude` and `ShapeDef::Revolve`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Profile {
+  /// A circle centered on the origin.
+  Circle { radius: f32 },
+  /// A rectangle centered on the origin, `width` along u and `height` along v.
+  Rect { width: f32, height: f32 },
+  /// A simple polygon through `points`, in either winding order. Concave
+  /// polygons are supported, but the edges must not cross each other, or the
+  /// polygon may be left empty (see `check_polygon`).
+  Polygon { points: Vec<[f32; 2]> },
+  /// A profile moved by `offset`.
+  Translate {
+    offset:  [f32; 2],
+    profile: Box<Profile>,
+  },
+  Union(Box<Profile>, Box<Profile>),
+  Difference(Box<Profile>, Box<Profile>),
+  Intersection(Box<Profile>, Box<Profile>),
+}
+
+impl Profile {
+  /// Compiles the 2D distance field of the profile, evaluated at the
+  /// coordinates `u` and `v`.
+  pub fn compile(
+    &self,
+    u: Node,
+    v: Node,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
+  ) -> Node {
+    match self {
+      Profile::Circle { radius } => {
+        if *radius * 2.0 < settings.min_voxel_size {
+          return ctx.constant(1.0);
+        }
+
+        let r = ctx.constant((*radius).into());
+        let length = nso_length(&[u, v], ctx);
+        ctx.sub(length, r).unwrap()
+      }
+      Profile::Rect { width, height } => {
+        if *width < settings.min_voxel_size || *height < settings.min_voxel_size
+        {
+          return ctx.constant(1.0);
+        }
+
+        let half_width = ctx.constant((*width / 2.0).into());
+        let half_height = ctx.constant((*height / 2.0).into());
+        let abs_u = ctx.abs(u).unwrap();
+        let abs_v = ctx.abs(v).unwrap();
+        let slab_u = ctx.sub(abs_u, half_width).unwrap();
+        let slab_v = ctx.sub(abs_v, half_height).unwrap();
+        nso_orthogonal_intersection(&[slab_u, slab_v], ctx)
+      }
+      Profile::Polygon { points } => {
+        let Some(points) = clean_polygon(points) else {
+          return ctx.constant(1.0);
+        };
+
+        let boundary = polygon_boundary(&points, u, v, ctx);
+        if is_convex(&points) {
+          return convex_polygon(&points, boundary, u, v, ctx);
+        }
+
+        // the union of the triangles only has the right distance outside, so
+        // use it for the sign and the boundary for the magnitude: this is
+        // `boundary` outside and `-boundary` inside
+        let Some(triangles) = triangulate(&points) else {
+          return ctx.constant(1.0);
+        };
+        let mut inside = None;
+        for triangle in triangles {
+          let triangle_boundary = polygon_boundary(&triangle, u, v, ctx);
+          let triangle =
+            convex_polygon(&triangle, triangle_boundary, u, v, ctx);
+          inside = Some(match inside {
+            Some(inside) => ctx.min(inside, triangle).unwrap(),
+            None => triangle,
+          });
+        }
+        let zero = ctx.constant(0.0);
+        let two = ctx.constant(2.0);
+        let outside = ctx.max(inside.unwrap(), zero).unwrap();
+        let outside = ctx.mul(outside, two).unwrap();
+        ctx.sub(outside, boundary).unwrap()
+      }
+      Profile::Translate { offset, profile } => {
+        let offset_u = ctx.constant(offset[0].into());
+        let offset_v = ctx.constant(offset[1].into());
+        let u = ctx.sub(u, offset_u).unwrap();
+        let v = ctx.sub(v, offset_v).unwrap();
+        profile.compile(u, v, ctx, settings)
+      }
+      Profile::Union(a, b) => {
+        let a = a.compile(u, v, ctx, settings);
+        let b = b.compile(u, v, ctx, settings);
+        nso_union(a, b, ctx)
+      }
+      Profile::Difference(a, b) => {
+        let a = a.compile(u, v, ctx, settings);
+        let b = b.compile(u, v, ctx, settings);
+        nso_difference(a, b, ctx)
+      }
+      Profile::Intersection(a, b) => {
+        let a = a.compile(u, v, ctx, settings);
+        let b = b.compile(u, v, ctx, settings);
+        nso_intersection(a, b, ctx)
+      }
+    }
+  }
+}
+
+/// Twice the signed area of a polygon, positive for counter-clockwise winding.
+fn signed_area(points: &[[f32; 2]]) -> f32 {
+  let mut area = 0.0;
+  for (i, a) in points.iter().enumerate() {
+    let b = points[(i + 1) % points.len()];
+    area += a[0] * b[1] - b[0] * a[1];
+  }
+  area
+}
+
+/// The cross product of the edges `a -> b` and `b -> c`, positive when the
+/// path turns counter-clockwise at `b`.
+fn turn(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+  (b[0] - a[0]) * (c[1] - b[1]) - (b[1] - a[1]) * (c[0] - b[0])
+}
+
+/// Whether the path `a -> b -> c` goes straight on or doubles back at `b`,
+/// allowing for rounding.
+fn is_straight(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool {
+  let ab = glam::Vec2::from(b) - glam::Vec2::from(a);
+  let bc = glam::Vec2::from(c) - glam::Vec2::from(b);
+  turn(a, b, c).abs() <= 1e-6 * ab.length() * bc.length()
+}
+
+/// The index of a vertex of a closed polygon that `is_straight`, if any.
+fn find_straight(points: &[[f32; 2]]) -> Option<usize> {
+  let len = points.len();
+  (0..len).find(|i| {
+    is_straight(
+      points[(i + len - 1) % len],
+      points[*i],
+      points[(i + 1) % len],
+    )
+  })
+}
+
+/// Removes repeated points and points the outline passes straight through or
+/// doubles back at, and puts a polygon in counter-clockwise order, or returns
+/// `None` if it has no area.
+fn clean_polygon(points: &[[f32; 2]]) -> Option<Vec<[f32; 2]>> {
+  let mut cleaned: Vec<[f32; 2]> = Vec::with_capacity(points.len());
+  for point in points {
+    if cleaned.last() != Some(point) {
+      cleaned.push(*point);
+    }
+  }
+  while cleaned.len() > 1 && cleaned.first() == cleaned.last() {
+    cleaned.pop();
+  }
+  while cleaned.len() >= 3 {
+    let Some(straight) = find_straight(&cleaned) else {
+      break;
+    };
+    cleaned.remove(straight);
+  }
+
+  let area = signed_area(&cleaned);
+  if cleaned.len() < 3 || area.abs() <= f32::EPSILON {
+    return None;
+  }
+  if area < 0.0 {
+    cleaned.reverse();
+  }
+  Some(cleaned)
+}
+
+/// Whether a counter-clockwise polygon is convex.
+fn is_convex(points: &[[f32; 2]]) -> bool {
+  (0..points.len()).all(|i| {
+    let a = points[i];
+    let b = points[(i + 1) % points.len()];
+    let c = points[(i + 2) % points.len()];
+    turn(a, b, c) >= 0.0
+  })
+}
+
+/// Splits a counter-clockwise simple polygon into triangles by ear clipping,
+/// or returns `None` if it runs out of ears, as polygons whose edges cross
+/// can.
+fn triangulate(points: &[[f32; 2]]) -> Option<Vec<[[f32; 2]; 3]>> {
+  let mut remaining = points.to_vec();
+  let mut triangles = Vec::with_capacity(points.len() - 2);
+  while remaining.len() > 3 {
+    // clipping can leave points the outline passes straight through, which
+    // would only make empty ears
+    if let Some(straight) = find_straight(&remaining) {
+      remaining.remove(straight);
+      continue;
+    }
+
+    let len = remaining.len();
+    let ear = (0..len).find(|i| {
+      let a = remaining[(i + len - 1) % len];
+      let b = remaining[*i];
+      let c = remaining[(i + 1) % len];
+      if turn(a, b, c) <= 0.0 {
+        return false;
+      }
+      // an ear can't contain any of the other points
+      remaining.iter().all(|p| {
+        *p == a
+          || *p == b
+          || *p == c
+          || turn(a, b, *p) < 0.0
+          || turn(b, c, *p) < 0.0
+          || turn(c, a, *p) < 0.0
+      })
+    })?;
+    let a = remaining[(ear + len - 1) % len];
+    let c = remaining[(ear + 1) % len];
+    triangles.push([a, remaining[ear], c]);
+    remaining.remove(ear);
+  }
+  if !is_straight(remaining[0], remaining[1], remaining[2]) {
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+  }
+  Some(triangles)
+}
+
+/// Checks that a polygon can be filled by `Profile::Polygon`: it needs some
+/// area, and it must split into triangles, which some polygons whose edges
+/// cross can't.
+pub fn check_polygon(points: &[[f32; 2]]) -> Result<()> {
+  let points =
+    clean_polygon(points).ok_or(Error::msg("polygon has no area"))?;
+  if triangulate(&points).is_none() {
+    return Err(Error::msg(
+      "polygon can't be split into triangles, as its edges cross",
+    ));
+  }
+  Ok(())
+}
+
+/// Computes the unsigned distance to the edges of a closed polygon.
+fn polygon_boundary(
+  points: &[[f32; 2]],
+  u: Node,
+  v: Node,
+  ctx: &mut Context,
+) -> Node {
+  let zero = ctx.constant(0.0);
+  let one = ctx.constant(1.0);
+  let mut boundary = None;
+  for (i, a) in points.iter().enumerate() {
+    let b = points[(i + 1) % points.len()];
+    let edge = [b[0] - a[0], b[1] - a[1]];
+
+    // project onto the edge, clamped to its endpoints
+    let start_u = ctx.constant(a[0].into());
+    let start_v = ctx.constant(a[1].into());
+    let edge_u = ctx.constant(edge[0].into());
+    let edge_v = ctx.constant(edge[1].into());
+    let inv_length_sq =
+      ctx.constant((1.0 / (edge[0] * edge[0] + edge[1] * edge[1])).into());
+    let rel_u = ctx.sub(u, start_u).unwrap();
+    let rel_v = ctx.sub(v, start_v).unwrap();
+    let along_u = ctx.mul(rel_u, edge_u).unwrap();
+    let along_v = ctx.mul(rel_v, edge_v).unwrap();
+    let along = ctx.add(along_u, along_v).unwrap();
+    let along = ctx.mul(along, inv_length_sq).unwrap();
+    let along = ctx.max(along, zero).unwrap();
+    let along = ctx.min(along, one).unwrap();
+
+    let closest_u = ctx.mul(edge_u, along).unwrap();
+    let closest_v = ctx.mul(edge_v, along).unwrap();
+    let offset_u = ctx.sub(rel_u, closest_u).unwrap();
+    let offset_v = ctx.sub(rel_v, closest_v).unwrap();
+    let distance = nso_length(&[offset_u, offset_v], ctx);
+    boundary = Some(match boundary {
+      Some(boundary) => ctx.min(boundary, distance).unwrap(),
+      None => distance,
+    });
+  }
+  boundary.unwrap()
+}
+
+/// Computes the exact distance field of a counter-clockwise convex polygon,
+/// given the unsigned distance to its edges.
+fn convex_polygon(
+  points: &[[f32; 2]],
+  boundary: Node,
+  u: Node,
+  v: Node,
+  ctx: &mut Context,
+) -> Node {
+  // the largest distance past any edge's line is negative exactly inside,
+  // where it's also the negated distance to the boundary
+  let mut planes = None;
+  for (i, a) in points.iter().enumerate() {
+    let b = points[(i + 1) % points.len()];
+    let edge = glam::Vec2::new(b[0] - a[0], b[1] - a[1]);
+    let normal = -edge.perp().normalize();
+    let normal_u = ctx.constant(normal.x.into());
+    let normal_v = ctx.constant(normal.y.into());
+    let offset = ctx.constant((normal.x * a[0] + normal.y * a[1]).into());
+    let along_u = ctx.mul(u, normal_u).unwrap();
+    let along_v = ctx.mul(v, normal_v).unwrap();
+    let plane = ctx.add(along_u, along_v).unwrap();
+    let plane = ctx.sub(plane, offset).unwrap();
+    planes = Some(match planes {
+      Some(planes) => ctx.max(planes, plane).unwrap(),
+      None => plane,
+    });
+  }
+  let zero = ctx.constant(0.0);
+  let two = ctx.constant(2.0);
+  let inside = ctx.min(planes.unwrap(), zero).unwrap();
+  let inside = ctx.mul(inside, two).unwrap();
+  ctx.add(boundary, inside).unwrap()
+}
@@ -1,7 +1,19 @@
-use rhai::{Dynamic, Engine, Scope};
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
 use anyhow::{Result, Error};
 
-use crate::{builder, shape::Shape};
+use crate::{
+  builder,
+  import::{Heightmap, SdfGrid, SDF_GRID_MAX_RESOLUTION},
+  profile::{check_polygon, Profile},
+  shape::{Axis, HexOrientation, Shape},
+};
 
 #[derive(Clone)]
 pub struct ShapeWithTranslate(Shape, [f32; 3]);
@@ -17,19 +29,333 @@ pub fn attach_translate(
   ShapeWithTranslate(shape, pos)
 }
 
+pub fn checked_matrix_transform(
+  shape: Shape,
+  matrix: Array,
+) -> Result<Shape, Box<EvalAltResult>> {
+  if matrix.len() != 16 {
+    return Err(
+      format!("matrix_transform expects 16 values, got {}", matrix.len())
+        .into(),
+    );
+  }
+  let mut values = [0.0; 16];
+  for (i, val) in matrix.into_iter().enumerate() {
+    values[i] = val
+      .as_float()
+      .map_err(|_| format!("matrix_transform value {} is not a float", i))?;
+  }
+  builder::matrix_transform(shape, values)
+    .map_err(|error| format!("matrix_transform: {}", error).into())
+}
+
+pub fn hex_prism_with_orientation(
+  radius: f32,
+  height: f32,
+  orientation: &str,
+) -> Result<Shape, Box<EvalAltResult>> {
+  let orientation = match orientation {
+    "pointy" => HexOrientation::Pointy,
+    "flat" => HexOrientation::Flat,
+    _ => {
+      return Err(
+        format!(
+          "hex_prism orientation must be \"pointy\" or \"flat\", got \"{}\"",
+          orientation
+        )
+        .into(),
+      )
+    }
+  };
+  Ok(builder::hex_prism(radius, height, orientation))
+}
+
+fn parse_color(color: Array) -> Result<[u8; 3], Box<EvalAltResult>> {
+  if color.len() != 3 {
+    return Err(
+      format!("color expects 3 values, got {}", color.len()).into(),
+    );
+  }
+  let mut rgb = [0; 3];
+  for (i, val) in color.into_iter().enumerate() {
+    rgb[i] = val
+      .as_int()
+      .ok()
+      .and_then(|val| u8::try_from(val).ok())
+      .ok_or_else(|| format!("color value {} is not an integer 0-255", i))?;
+  }
+  Ok(rgb)
+}
+
+pub fn checked_material(
+  shape: Shape,
+  id: i32,
+  roughness: f32,
+  metallic: f32,
+  emissive: Array,
+) -> Result<Shape, Box<EvalAltResult>> {
+  let id = u32::try_from(id)
+    .map_err(|_| format!("material id must not be negative, got {}", id))?;
+  if emissive.len() != 3 {
+    return Err(
+      format!("material expects 3 emissive values, got {}", emissive.len())
+        .into(),
+    );
+  }
+  let mut values = [0.0; 3];
+  for (i, val) in emissive.into_iter().enumerate() {
+    values[i] = val
+      .as_float()
+      .map_err(|_| format!("material emissive value {} is not a float", i))?;
+  }
+  Ok(builder::material(shape, id, roughness, metallic, values))
+}
+
+pub fn polygon_from_points(
+  points: Array,
+) -> Result<Profile, Box<EvalAltResult>> {
+  let mut parsed = Vec::with_capacity(points.len());
+  for (i, point) in points.into_iter().enumerate() {
+    let point = point
+      .try_cast::<Array>()
+      .filter(|point| point.len() == 2)
+      .ok_or_else(|| format!("polygon point {} is not a [u, v] array", i))?;
+    let mut values = [0.0; 2];
+    for (j, val) in point.into_iter().enumerate() {
+      values[j] = val
+        .as_float()
+        .map_err(|_| format!("polygon point {} is not a [u, v] array", i))?;
+    }
+    parsed.push(values);
+  }
+  if parsed.len() < 3 {
+    return Err(
+      format!("polygon needs at least 3 points, got {}", parsed.len()).into(),
+    );
+  }
+  check_polygon(&parsed).map_err(|error| error.to_string())?;
+  Ok(builder::polygon(parsed))
+}
+
+/// Resolves a path given by a script inside the asset directory, refusing any
+/// path that leads out of it, including through symlinks.
+fn resolve_asset(
+  asset_root: Option<&Path>,
+  path: &str,
+) -> Result<PathBuf, Box<EvalAltResult>> {
+  let asset_root = asset_root.ok_or_else(|| {
+    format!("can't read \"{}\" without an asset directory", path)
+  })?;
+  let asset_root = asset_root.canonicalize().map_err(|e| {
+    format!("asset directory \"{}\": {}", asset_root.display(), e)
+  })?;
+  let resolved = asset_root
+    .join(path)
+    .canonicalize()
+    .map_err(|e| format!("failed to read \"{}\": {}", path, e))?;
+  if !resolved.starts_with(&asset_root) {
+    return Err(format!("\"{}\" is outside the asset directory", path).into());
+  }
+  Ok(resolved)
+}
+
+/// The grids a script has imported, by resolved path and resolution, so that
+/// importing a file again doesn't sample it again.
+pub type GridCache = HashMap<(PathBuf, u32), Arc<SdfGrid>>;
+
+pub fn import_obj(
+  asset_root: Option<&Path>,
+  grids: &mut GridCache,
+  path: &str,
+  resolution: i32,
+) -> Result<Shape, Box<EvalAltResult>> {
+  if resolution < 1 || resolution as u32 > SDF_GRID_MAX_RESOLUTION {
+    return Err(
+      format!(
+        "import_obj resolution must be between 1 and {}, got {}",
+        SDF_GRID_MAX_RESOLUTION, resolution
+      )
+      .into(),
+    );
+  }
+  let resolved = resolve_asset(asset_root, path)?;
+  let key = (resolved, resolution as u32);
+  if let Some(grid) = grids.get(&key) {
+    return Ok(builder::imported_mesh(grid.clone()));
+  }
+  let source = std::fs::read_to_string(&key.0)
+    .map_err(|e| format!("failed to read \"{}\": {}", path, e))?;
+  let grid = SdfGrid::from_obj(&source, key.1)
+    .map_err(|e| format!("failed to import \"{}\": {}", path, e))?;
+  let grid = grids.entry(key).or_insert(Arc::new(grid));
+  Ok(builder::imported_mesh(grid.clone()))
+}
+
+fn parse_axis(axis: &str) -> Result<Axis, Box<EvalAltResult>> {
+  match axis {
+    "x" => Ok(Axis::X),
+    "y" => Ok(Axis::Y),
+    "z" => Ok(Axis::Z),
+    _ => Err(
+      format!("axis must be \"x\", \"y\" or \"z\", got \"{}\"", axis).into(),
+    ),
+  }
+}
+
+pub fn mirror_axes(
+  shape: Shape,
+  axes: &str,
+  offset: Array,
+) -> Result<Shape, Box<EvalAltResult>> {
+  if offset.len() != 3 {
+    return Err(
+      format!("mirror offset expects 3 values, got {}", offset.len()).into(),
+    );
+  }
+  let mut enabled = [false; 3];
+  for axis in axes.chars() {
+    enabled[parse_axis(&axis.to_string())?.index()] = true;
+  }
+  let mut pos = [0.0; 3];
+  for (i, val) in offset.into_iter().enumerate() {
+    pos[i] = val
+      .as_float()
+      .map_err(|_| format!("mirror offset value {} is not a float", i))?;
+  }
+  Ok(builder::mirror(shape, enabled, pos))
+}
+
 pub fn eval(
   code: &str,
+) -> Result<Vec<(Shape, [f32; 3])>> {
+  eval_with_tables(code, &HashMap::new(), None)
+}
+
+/// Evaluates a script like `eval`, letting it read files with `import_obj`
+/// from within `asset_root`.
+pub fn eval_with_assets(
+  code: &str,
+  asset_root: &Path,
+) -> Result<Vec<(Shape, [f32; 3])>> {
+  eval_with_tables(code, &HashMap::new(), Some(asset_root))
+}
+
+/// Evaluates a script like `eval`, with `tables` available to it by name, e.g.
+/// as `heightfield("terrain", width, depth, scale)`, and files within
+/// `asset_root` readable with `import_obj`. Without an asset root, scripts
+/// can't read files at all. Shapes share the tables' samples rather than
+/// copying them.
+pub fn eval_with_tables(
+  code: &str,
+  tables: &HashMap<String, Arc<Heightmap>>,
+  asset_root: Option<&Path>,
 ) -> Result<Vec<(Shape, [f32; 3])>> {
   let mut engine = Engine::new();
 
   engine.register_type::<Shape>();
   engine.register_fn("sphere", builder::sphere);
   engine.register_fn("box", builder::box_);
+  engine.register_fn("cheap_box", builder::cheap_box);
+  engine.register_fn("rounded_box", builder::rounded_box);
   engine.register_fn("cube", builder::cube);
+  engine.register_fn("cylinder", builder::cylinder);
+  engine.register_fn("cone", builder::cone);
+  engine.register_fn("torus", builder::torus);
+  engine.register_fn("capsule", builder::capsule);
+  engine.register_fn("plane", builder::plane);
+  engine.register_fn("ellipsoid", builder::ellipsoid);
+  engine.register_fn("hex_prism", |radius: f32, height: f32| {
+    builder::hex_prism(radius, height, HexOrientation::default())
+  });
+  engine.register_fn("hex_prism", hex_prism_with_orientation);
   
+  let asset_root = asset_root.map(Path::to_path_buf);
+  let grids = RefCell::new(GridCache::new());
+  engine.register_fn("import_obj", move |path: &str, resolution: i32| {
+    let mut grids = grids.borrow_mut();
+    import_obj(asset_root.as_deref(), &mut grids, path, resolution)
+  });
+  let tables = tables.clone();
+  engine.register_fn(
+    "heightfield",
+    move |name: &str,
+          width: f32,
+          depth: f32,
+          scale: f32|
+          -> Result<Shape, Box<EvalAltResult>> {
+      let samples = tables
+        .get(name)
+        .ok_or_else(|| format!("no data table named \"{}\"", name))?;
+      Ok(builder::heightfield(width, depth, samples.clone(), scale))
+    },
+  );
+  engine.register_type::<Profile>();
+  engine.register_fn("circle", builder::circle);
+  engine.register_fn("rect", builder::rect);
+  engine.register_fn("polygon", polygon_from_points);
+  engine.register_fn("translate", builder::translate_profile);
+  engine.register_fn("union", builder::profile_union);
+  engine.register_fn("difference", builder::profile_difference);
+  engine.register_fn("intersection", builder::profile_intersection);
+  engine.register_fn("extrude", builder::extrude);
+  engine.register_fn("revolve", |profile: Profile| {
+    builder::revolve(profile, Axis::Y)
+  });
+  engine.register_fn(
+    "revolve",
+    |profile: Profile, axis: &str| -> Result<Shape, Box<EvalAltResult>> {
+      Ok(builder::revolve(profile, parse_axis(axis)?))
+    },
+  );
   engine.register_fn("translate", builder::translate);
   engine.register_fn("scale", builder::scale);
-  engine.register_fn("matrix_transform", builder::matrix_transform);
+  engine.register_fn("matrix_transform", checked_matrix_transform);
+  engine.register_fn("rotate_x", builder::rotate_x);
+  engine.register_fn("rotate_y", builder::rotate_y);
+  engine.register_fn("rotate_z", builder::rotate_z);
+  engine.register_fn("rotate_euler", builder::rotate_euler);
+  engine.register_fn("rotate_axis", builder::rotate_axis);
+  engine.register_fn("rotate_quat", builder::rotate_quat);
+  engine.register_fn("repeat", builder::repeat);
+  engine.register_fn(
+    "repeat_finite",
+    |shape: Shape, x: f32, y: f32, z: f32, nx: i32, ny: i32, nz: i32| {
+      let count = [nx, ny, nz].map(|n| n.max(0) as u32);
+      builder::repeat_finite(shape, [x, y, z], count)
+    },
+  );
+  engine.register_fn("mirror", |shape: Shape, axes: &str| {
+    mirror_axes(shape, axes, vec![Dynamic::from_float(0.0); 3])
+  });
+  engine.register_fn("mirror", mirror_axes);
+  engine.register_fn(
+    "radial_symmetry",
+    |shape: Shape,
+     axis: &str,
+     count: i32|
+     -> Result<Shape, Box<EvalAltResult>> {
+      let axis = parse_axis(axis)?;
+      Ok(builder::radial_symmetry(shape, axis, count.max(0) as u32))
+    },
+  );
+  engine.register_fn("twist", builder::twist);
+  engine.register_fn("bend", builder::bend);
+  engine.register_fn("taper", builder::taper);
+  engine.register_fn("offset", builder::offset);
+  engine.register_fn("shell", builder::shell);
+  engine.register_fn("elongate", builder::elongate);
+  engine.register_fn(
+    "displace",
+    |shape: Shape, seed: i32, frequency: f32, amplitude: f32, octaves: i32| {
+      builder::displace(
+        shape,
+        seed as u32,
+        frequency,
+        amplitude,
+        octaves.max(1) as u32,
+      )
+    },
+  );
   engine.register_fn("recolor", |shape: Shape, r: i32, g: i32, b: i32| {
     builder::recolor(
       shape,
@@ -38,11 +364,110 @@ pub fn eval(
       (b % 256).try_into().unwrap(),
     )
   });
+  engine.register_fn(
+    "gradient",
+    |shape: Shape,
+     from: Array,
+     to: Array,
+     axis: &str|
+     -> Result<Shape, Box<EvalAltResult>> {
+      let (from, to) = (parse_color(from)?, parse_color(to)?);
+      let axis = parse_axis(axis)?;
+      Ok(builder::linear_gradient(shape, from, to, axis, -1.0, 1.0))
+    },
+  );
+  engine.register_fn(
+    "gradient",
+    |shape: Shape,
+     from: Array,
+     to: Array,
+     axis: &str,
+     start: f32,
+     end: f32|
+     -> Result<Shape, Box<EvalAltResult>> {
+      let (from, to) = (parse_color(from)?, parse_color(to)?);
+      let axis = parse_axis(axis)?;
+      Ok(builder::linear_gradient(shape, from, to, axis, start, end))
+    },
+  );
+  engine.register_fn(
+    "radial_gradient",
+    |shape: Shape,
+     from: Array,
+     to: Array,
+     radius: f32|
+     -> Result<Shape, Box<EvalAltResult>> {
+      let (from, to) = (parse_color(from)?, parse_color(to)?);
+      Ok(builder::radial_gradient(shape, from, to, radius))
+    },
+  );
+  engine.register_fn(
+    "stripes",
+    |shape: Shape,
+     a: Array,
+     b: Array,
+     axis: &str,
+     width: f32|
+     -> Result<Shape, Box<EvalAltResult>> {
+      let (a, b) = (parse_color(a)?, parse_color(b)?);
+      let axis = parse_axis(axis)?;
+      Ok(builder::stripes(shape, a, b, axis, width))
+    },
+  );
+  engine.register_fn(
+    "checker",
+    |shape: Shape,
+     a: Array,
+     b: Array,
+     size: f32|
+     -> Result<Shape, Box<EvalAltResult>> {
+      Ok(builder::checker(
+        shape,
+        parse_color(a)?,
+        parse_color(b)?,
+        size,
+      ))
+    },
+  );
+  engine.register_fn(
+    "noise_tint",
+    |shape: Shape,
+     rgb: Array,
+     strength: f32,
+     seed: i32,
+     frequency: f32,
+     octaves: i32|
+     -> Result<Shape, Box<EvalAltResult>> {
+      Ok(builder::noise_tint(
+        shape,
+        parse_color(rgb)?,
+        strength,
+        seed as u32,
+        frequency,
+        octaves.max(1) as u32,
+      ))
+    },
+  );
+  engine.register_fn("material", checked_material);
+  engine.register_fn(
+    "material",
+    |shape: Shape,
+     id: i32,
+     roughness: f32,
+     metallic: f32|
+     -> Result<Shape, Box<EvalAltResult>> {
+      let emissive = vec![Dynamic::from_float(0.0); 3];
+      checked_material(shape, id, roughness, metallic, emissive)
+    },
+  );
   engine.register_fn("abbreviate", builder::abbreviate);
   engine.register_fn("union", builder::union);
   engine.register_fn("difference", builder::difference);
   engine.register_fn("intersection", builder::intersection);
   engine.register_fn("replacement", builder::replacement);
+  engine.register_fn("smooth_union", builder::smooth_union);
+  engine.register_fn("smooth_difference", builder::smooth_difference);
+  engine.register_fn("smooth_intersection", builder::smooth_intersection);
   engine.register_fn("shape", attach_translate);
 
   let ast = engine.compile(code)?;
@@ -66,11 +491,153 @@ pub fn eval(
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::builder::sphere;
+  use crate::{
+    builder::{
+      linear_gradient, polygon, profile_difference, rect, revolve, sphere,
+      translate_profile,
+    },
+    shape::ShapeDef,
+  };
 
   #[test]
   fn test_eval() {
     let shape = eval("[shape(sphere(1.0), [0.0, 0.0, 0.0])]").unwrap();
     assert_eq!(shape, vec![(sphere(1.0), [0.0, 0.0, 0.0])]);
   }
+
+  #[test]
+  fn test_eval_singular_matrix_transform() {
+    let result = eval(
+      "[shape(matrix_transform(sphere(1.0), [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, \
+       0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]), [0.0, 0.0, 0.0])]",
+    );
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_eval_mirror() {
+    let code = |args: &str| {
+      format!("[shape(mirror(sphere(1.0), {}), [0.0, 0.0, 0.0])]", args)
+    };
+    let centered = builder::mirror(sphere(1.0), [true, false, true], [0.0; 3]);
+    let shape = eval(&code("\"xz\"")).unwrap();
+    assert_eq!(shape, vec![(centered, [0.0; 3])]);
+    assert!(eval(&code("\"x\", [1.0, 0.0, 0.0]")).is_ok());
+    assert!(eval(&code("\"x\", [1.0, 0.0]")).is_err());
+    assert!(eval(&code("\"x\", [1.0, 0.0, 0.0, 0.0]")).is_err());
+  }
+
+  #[test]
+  fn test_eval_with_tables() {
+    let samples =
+      Arc::new(Heightmap::new(2, 2, vec![0.0, 1.0, 0.0, 1.0]).unwrap());
+    let tables = HashMap::from([("ramp".to_string(), samples.clone())]);
+    let code = "[shape(heightfield(\"ramp\", 4.0, 4.0, 2.0), [0.0, 0.0, 0.0])]";
+    let shape = eval_with_tables(code, &tables, None).unwrap();
+    assert_eq!(shape, vec![(
+      builder::heightfield(4.0, 4.0, samples.clone(), 2.0),
+      [0.0, 0.0, 0.0]
+    )]);
+    // the shape shares the table's samples instead of copying them
+    let Shape::ShapeDef(ShapeDef::Heightfield {
+      samples: shared, ..
+    }) = &shape[0].0
+    else {
+      panic!("expected a heightfield");
+    };
+    assert!(Arc::ptr_eq(shared, &samples));
+    assert!(eval(code).is_err());
+  }
+
+  #[test]
+  fn test_import_obj_stays_in_asset_root() {
+    let root = std::env::temp_dir().join("planiscope_test_import_obj");
+    let assets = root.join("assets");
+    std::fs::create_dir_all(&assets).unwrap();
+    let tetrahedron = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 3 2\nf 1 2 \
+                       4\nf 1 4 3\nf 2 3 4\n";
+    std::fs::write(assets.join("tetrahedron.obj"), tetrahedron).unwrap();
+    std::fs::write(root.join("secret.obj"), tetrahedron).unwrap();
+
+    let script = |path: &str| {
+      format!("[shape(import_obj(\"{}\", 4), [0.0, 0.0, 0.0])]", path)
+    };
+    assert!(eval_with_assets(&script("tetrahedron.obj"), &assets).is_ok());
+    let code =
+      "[shape(import_obj(\"tetrahedron.obj\", 100000), [0.0, 0.0, 0.0])]";
+    assert!(eval_with_assets(code, &assets).is_err());
+
+    // importing the same file twice samples it once
+    let code = "let a = import_obj(\"tetrahedron.obj\", 4); [shape(a, [0.0, \
+                0.0, 0.0]), shape(import_obj(\"./tetrahedron.obj\", 4), [1.0, \
+                0.0, 0.0])]";
+    let shapes = eval_with_assets(code, &assets).unwrap();
+    let grids = shapes
+      .iter()
+      .map(|(shape, _)| match shape {
+        Shape::ShapeDef(ShapeDef::ImportedMesh { grid }) => grid.clone(),
+        _ => panic!("expected an imported mesh"),
+      })
+      .collect::<Vec<_>>();
+    assert!(Arc::ptr_eq(&grids[0], &grids[1]));
+    assert!(eval_with_assets(&script("../secret.obj"), &assets).is_err());
+    let absolute = root.join("secret.obj");
+    let absolute = script(&absolute.to_string_lossy());
+    assert!(eval_with_assets(&absolute, &assets).is_err());
+    // without an asset directory, nothing can be read
+    assert!(eval(&script("tetrahedron.obj")).is_err());
+  }
+
+  #[test]
+  fn test_eval_color_patterns() {
+    let shape = eval(
+      "[shape(gradient(sphere(1.0), [255, 0, 0], [0, 0, 255], \"y\"), [0.0, \
+       0.0, 0.0])]",
+    )
+    .unwrap();
+    let gradient = linear_gradient(
+      sphere(1.0),
+      [255, 0, 0],
+      [0, 0, 255],
+      Axis::Y,
+      -1.0,
+      1.0,
+    );
+    assert_eq!(shape, vec![(gradient, [0.0, 0.0, 0.0])]);
+
+    let result = eval(
+      "[shape(checker(sphere(1.0), [256, 0, 0], [0, 0, 0], 0.5), [0.0, 0.0, \
+       0.0])]",
+    );
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_eval_profiles() {
+    let shape = eval(
+      "let pot = difference(polygon([[0.0, 0.0], [1.0, 0.0], [1.5, 2.0], \
+       [0.0, 2.0]]), translate(rect(2.0, 2.0), 0.0, 1.2));
+       [shape(revolve(pot), [0.0, 0.0, 0.0])]",
+    )
+    .unwrap();
+    let pot = profile_difference(
+      polygon(vec![[0.0, 0.0], [1.0, 0.0], [1.5, 2.0], [0.0, 2.0]]),
+      translate_profile(rect(2.0, 2.0), 0.0, 1.2),
+    );
+    assert_eq!(shape, vec![(revolve(pot, Axis::Y), [0.0, 0.0, 0.0])]);
+
+    // polygons that can't be filled are rejected rather than left empty
+    let extruded = |points: &str| {
+      eval(&format!(
+        "[shape(extrude(polygon({}), 1.0), [0.0, 0.0, 0.0])]",
+        points
+      ))
+    };
+    assert!(extruded("[[0.0, 0.0], [1.0, 1.0], [2.0, 2.0]]").is_err());
+    assert!(extruded(
+      "[[1.0, 3.0], [4.0, 0.0], [1.0, 4.0], [3.0, 4.0], [0.0, 0.0], [4.0, \
+       1.0]]"
+    )
+    .is_err());
+  }
 }
@@ -1,6 +1,15 @@
+use anyhow::{anyhow, Result};
 use fidget::{context::Node, Context};
 
-use crate::{comp::CompilationSettings, nso::*};
+use std::sync::Arc;
+
+use crate::{
+  comp::CompilationSettings,
+  import::{Heightmap, SdfGrid},
+  material::{Material, MaterialTable, DEFAULT_MATERIAL_ID},
+  nso::*,
+  profile::Profile,
+};
 
 /// A trait with methods for compiling Fidget nodes from shape definitions.
 pub trait ShapeLike {
@@ -20,18 +29,27 @@ pub trait ShapeLike {
     let shape = self.compile_solid(ctx, settings);
     nso_clamp(shape, ctx)
   }
-  /// Compiles the color field of a shape. The resulting value is the 24-bit
-  /// representation of the RGB color, divided by 24-bit maximum, and mapped to
-  /// the range `[0.1, 1.0]`.
+  /// Compiles the color fields of a shape, one per RGB channel, each in the
+  /// range `[0.0, 1.0]`. The fields are defined everywhere, not just inside
+  /// the shape, so they should be sampled on its surface.
   fn compile_color(
     &self,
     ctx: &mut Context,
     settings: &CompilationSettings,
+  ) -> [Node; 3];
+  /// Compiles the material id field of a shape. Like the color fields, it's
+  /// defined everywhere and should be sampled on the surface, where it's
+  /// rounded to the nearest id.
+  fn compile_material(
+    &self,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
   ) -> Node;
 }
 
 /// A shape.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Shape {
   /// A shape definition.
   ShapeDef(ShapeDef),
@@ -54,20 +72,151 @@ impl ShapeLike for Shape {
     &self,
     ctx: &mut Context,
     settings: &CompilationSettings,
-  ) -> Node {
+  ) -> [Node; 3] {
     match self {
       Shape::ShapeDef(shape_def) => shape_def.compile_color(ctx, settings),
       Shape::ShapeOp(shape_op) => shape_op.compile_color(ctx, settings),
     }
   }
+  fn compile_material(
+    &self,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
+  ) -> Node {
+    match self {
+      Shape::ShapeDef(shape_def) => shape_def.compile_material(ctx, settings),
+      Shape::ShapeOp(shape_op) => shape_op.compile_material(ctx, settings),
+    }
+  }
+}
+
+impl Shape {
+  /// Collects the materials assigned anywhere in the shape into `table`. If
+  /// an id is given more than one material, the outermost one is kept.
+  pub fn collect_materials(&self, table: &mut MaterialTable) {
+    match self {
+      Shape::ShapeDef(_) => {}
+      Shape::ShapeOp(ShapeOp::UnaryOp(unary_op, a)) => {
+        if let UnaryOp::Material { id, material } = unary_op {
+          table.entry(*id).or_insert(*material);
+        }
+        a.collect_materials(table);
+      }
+      Shape::ShapeOp(ShapeOp::BinaryOp(_, a, b)) => {
+        a.collect_materials(table);
+        b.collect_materials(table);
+      }
+    }
+  }
+
+  /// Checks that every operation in the shape can be compiled, returning an
+  /// error for operations that would collapse the shape to nothing, like a
+  /// singular matrix transform.
+  pub fn check(&self) -> Result<()> {
+    match self {
+      Shape::ShapeDef(_) => Ok(()),
+      Shape::ShapeOp(ShapeOp::UnaryOp(unary_op, a)) => {
+        if let UnaryOp::MatrixTransform { matrix } = unary_op {
+          if invert_affine(*matrix).is_none() {
+            return Err(anyhow!("matrix transform {:?} is singular", matrix));
+          }
+        }
+        a.check()
+      }
+      Shape::ShapeOp(ShapeOp::BinaryOp(_, a, b)) => {
+        a.check()?;
+        b.check()
+      }
+    }
+  }
 }
 
 /// A shape definition. Shape definitions are pre-defined primitives.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ShapeDef {
+  /// A sphere centered on the origin. Its field is the exact distance to the
+  /// surface, so offsets and blend radii around it are in world units.
   SpherePrimitive { radius: f32 },
   RectPrismPrimitive { x: f32, y: f32, z: f32 },
+  /// A box like `RectPrismPrimitive`, but using the cheaper Chebyshev
+  /// (max-of-axes) bound. This underestimates the distance outside the edges
+  /// and corners, so offsets and blends near them will be inaccurate.
+  CheapRectPrismPrimitive { x: f32, y: f32, z: f32 },
+  /// A box with edges and corners rounded off with radius `radius`. The box
+  /// keeps its outer size of `x`, `y` and `z`.
+  RoundedRectPrismPrimitive { x: f32, y: f32, z: f32, radius: f32 },
   CubePrimitive { size: f32 },
+  /// A cylinder along the y axis, centered on the origin.
+  CylinderPrimitive { radius: f32, height: f32 },
+  /// A cone along the y axis, centered on the origin, with its base at
+  /// `-height / 2` and its tip at `height / 2`.
+  ConePrimitive { radius: f32, height: f32 },
+  /// A torus lying in the xz plane. `major_radius` is the distance from the
+  /// origin to the center of the tube, and `minor_radius` is the radius of the
+  /// tube.
+  TorusPrimitive { major_radius: f32, minor_radius: f32 },
+  /// A capsule along the y axis, centered on the origin. `height` is the
+  /// length of the straight section between the two hemispherical caps.
+  CapsulePrimitive { radius: f32, height: f32 },
+  /// The half-space below a plane with normal `normal`, offset from the origin
+  /// by `offset` along the normal.
+  PlanePrimitive { normal: [f32; 3], offset: f32 },
+  /// An ellipsoid with radii `x`, `y` and `z`, centered on the origin.
+  EllipsoidPrimitive { x: f32, y: f32, z: f32 },
+  /// A hexagonal prism along the y axis, centered on the origin. `radius` is
+  /// the distance from the center to a corner, like `hexx::HexLayout`'s
+  /// `hex_size`. A `hexx` column of height `h` spans `0..h`, so translate by
+  /// `h / 2` on y to line up with one.
+  HexPrismPrimitive {
+    radius:      f32,
+    height:      f32,
+    orientation: HexOrientation,
+  },
+  /// A profile in the xz plane (`u` along x, `v` along z) extruded along the
+  /// y axis, centered on the origin.
+  Extrude { profile: Profile, height: f32 },
+  /// A profile revolved around an axis, like a lathe. The profile's `u` is the
+  /// distance from the axis and `v` is the position along it, so only the
+  /// part of the profile with `u >= 0` contributes.
+  Revolve { profile: Profile, axis: Axis },
+  /// An imported triangle mesh, sampled into a signed distance grid. The grid
+  /// is shared, so cloning the shape doesn't copy the samples.
+  ImportedMesh { grid: Arc<SdfGrid> },
+  /// Terrain over a `width` by `depth` footprint in the xz plane, centered on
+  /// the origin, with its surface at `scale` times the heightmap's height. The
+  /// terrain is solid below the surface and has vertical sides at the edges
+  /// of the footprint.
+  Heightfield {
+    width:   f32,
+    depth:   f32,
+    samples: Arc<Heightmap>,
+    scale:   f32,
+  },
+}
+
+/// The orientation of a hexagon in the xz plane, matching
+/// `hexx::HexOrientation` with hexx's 2D y axis mapped to z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HexOrientation {
+  /// Corners point along the z axis. This is the `hexx` default.
+  #[default]
+  Pointy,
+  /// Corners point along the x axis.
+  Flat,
+}
+
+impl HexOrientation {
+  /// The angles in the xz plane, measured from the x axis towards z, of the
+  /// normals of three non-parallel edges of the hexagon.
+  fn edge_normal_angles(&self) -> [f32; 3] {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_6};
+    match self {
+      HexOrientation::Pointy => [0.0, FRAC_PI_3, 2.0 * FRAC_PI_3],
+      HexOrientation::Flat => [FRAC_PI_6, FRAC_PI_2, 5.0 * FRAC_PI_6],
+    }
+  }
 }
 
 impl ShapeLike for ShapeDef {
@@ -89,55 +238,352 @@ impl ShapeLike for ShapeDef {
         let x_sq = ctx.square(x).unwrap();
         let y_sq = ctx.square(y).unwrap();
         let z_sq = ctx.square(z).unwrap();
-        let r_sq = ctx.square(r).unwrap();
         let sum = ctx.add(x_sq, y_sq).unwrap();
         let sum = ctx.add(sum, z_sq).unwrap();
-        ctx.sub(sum, r_sq).unwrap()
+        let length = ctx.sqrt(sum).unwrap();
+        ctx.sub(length, r).unwrap()
       },
-      Self::RectPrismPrimitive {x, y, z} => {
-        if *x < settings.min_voxel_size || *y < settings.min_voxel_size || *z < settings.min_voxel_size {
+      Self::RectPrismPrimitive { x, y, z } => {
+        if *x < settings.min_voxel_size
+          || *y < settings.min_voxel_size
+          || *z < settings.min_voxel_size
+        {
+          return ctx.constant(1.0);
+        }
+
+        let slabs = box_slabs([*x / 2.0, *y / 2.0, *z / 2.0], ctx);
+        nso_orthogonal_intersection(&slabs, ctx)
+      }
+      Self::CheapRectPrismPrimitive { x, y, z } => {
+        if *x < settings.min_voxel_size
+          || *y < settings.min_voxel_size
+          || *z < settings.min_voxel_size
+        {
           return ctx.constant(1.0);
         }
-        let half_x = ctx.constant((*x / 2.0).into());
-        let half_y = ctx.constant((*y / 2.0).into());
-        let half_z = ctx.constant((*z / 2.0).into());
 
+        let [x, y, z] = box_slabs([*x / 2.0, *y / 2.0, *z / 2.0], ctx);
+        let max_xy = ctx.max(x, y).unwrap();
+        ctx.max(max_xy, z).unwrap()
+      }
+      Self::RoundedRectPrismPrimitive { x, y, z, radius } => {
+        if *x < settings.min_voxel_size
+          || *y < settings.min_voxel_size
+          || *z < settings.min_voxel_size
+        {
+          return ctx.constant(1.0);
+        }
+
+        // shrink the box by the radius, then inflate it back out
+        let radius = radius.clamp(0.0, x.min(*y).min(*z) / 2.0);
+        let slabs = box_slabs(
+          [*x / 2.0 - radius, *y / 2.0 - radius, *z / 2.0 - radius],
+          ctx,
+        );
+        let shape = nso_orthogonal_intersection(&slabs, ctx);
+        let radius = ctx.constant(radius.into());
+        ctx.sub(shape, radius).unwrap()
+      }
+      Self::CubePrimitive { size } => Self::RectPrismPrimitive { x: *size, y: *size, z: *size }.compile_solid(ctx, settings),
+      Self::CylinderPrimitive { radius, height } => {
+        if *radius * 2.0 < settings.min_voxel_size
+          || *height < settings.min_voxel_size
+        {
+          return ctx.constant(1.0);
+        }
+
+        let r = ctx.constant((*radius).into());
+        let half_height = ctx.constant((*height / 2.0).into());
         let x = ctx.x();
         let y = ctx.y();
         let z = ctx.z();
-        let abs_x = ctx.abs(x).unwrap();
+        let radial = nso_length(&[x, z], ctx);
+        let radial = ctx.sub(radial, r).unwrap();
         let abs_y = ctx.abs(y).unwrap();
+        let axial = ctx.sub(abs_y, half_height).unwrap();
+        nso_orthogonal_intersection(&[radial, axial], ctx)
+      }
+      Self::ConePrimitive { radius, height } => {
+        if *radius * 2.0 < settings.min_voxel_size
+          || *height < settings.min_voxel_size
+        {
+          return ctx.constant(1.0);
+        }
+
+        // distance to the slanted side, as a line through the tip and the
+        // base rim in the radial plane
+        let slant = (*radius).hypot(*height);
+        let side_radial = ctx.constant((*height / slant).into());
+        let side_axial = ctx.constant((*radius / slant).into());
+        let half_height = ctx.constant((*height / 2.0).into());
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        let radial = nso_length(&[x, z], ctx);
+        let radial = ctx.mul(radial, side_radial).unwrap();
+        let from_tip = ctx.sub(y, half_height).unwrap();
+        let axial = ctx.mul(from_tip, side_axial).unwrap();
+        let side = ctx.add(radial, axial).unwrap();
+
+        let neg_y = ctx.neg(y).unwrap();
+        let base = ctx.sub(neg_y, half_height).unwrap();
+        ctx.max(side, base).unwrap()
+      }
+      Self::TorusPrimitive {
+        major_radius,
+        minor_radius,
+      } => {
+        if *minor_radius * 2.0 < settings.min_voxel_size {
+          return ctx.constant(1.0);
+        }
+
+        let major = ctx.constant((*major_radius).into());
+        let minor = ctx.constant((*minor_radius).into());
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        let radial = nso_length(&[x, z], ctx);
+        let radial = ctx.sub(radial, major).unwrap();
+        let tube = nso_length(&[radial, y], ctx);
+        ctx.sub(tube, minor).unwrap()
+      }
+      Self::CapsulePrimitive { radius, height } => {
+        if *radius * 2.0 < settings.min_voxel_size {
+          return ctx.constant(1.0);
+        }
+
+        let r = ctx.constant((*radius).into());
+        let half_height = ctx.constant((*height / 2.0).into());
+        let neg_half_height = ctx.constant((-*height / 2.0).into());
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        // distance along y to the nearest point on the central segment
+        let clamped_y = ctx.min(y, half_height).unwrap();
+        let clamped_y = ctx.max(clamped_y, neg_half_height).unwrap();
+        let y = ctx.sub(y, clamped_y).unwrap();
+        let length = nso_length(&[x, y, z], ctx);
+        ctx.sub(length, r).unwrap()
+      }
+      Self::PlanePrimitive { normal, offset } => {
+        let Some(normal) = glam::Vec3::from_array(*normal).try_normalize()
+        else {
+          return ctx.constant(1.0);
+        };
+
+        let normal_x = ctx.constant(normal.x.into());
+        let normal_y = ctx.constant(normal.y.into());
+        let normal_z = ctx.constant(normal.z.into());
+        let offset = ctx.constant((*offset).into());
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        let x = ctx.mul(x, normal_x).unwrap();
+        let y = ctx.mul(y, normal_y).unwrap();
+        let z = ctx.mul(z, normal_z).unwrap();
+        let dot = ctx.add(x, y).unwrap();
+        let dot = ctx.add(dot, z).unwrap();
+        ctx.sub(dot, offset).unwrap()
+      }
+      Self::EllipsoidPrimitive { x, y, z } => {
+        let smallest = x.min(*y).min(*z);
+        if smallest * 2.0 < settings.min_voxel_size {
+          return ctx.constant(1.0);
+        }
+
+        // distance in the space where the ellipsoid is a unit sphere, scaled
+        // by the smallest radius to keep it a bound on the true distance
+        let radius_x = ctx.constant((*x).into());
+        let radius_y = ctx.constant((*y).into());
+        let radius_z = ctx.constant((*z).into());
+        let smallest = ctx.constant(smallest.into());
+        let one = ctx.constant(1.0);
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        let x = ctx.div(x, radius_x).unwrap();
+        let y = ctx.div(y, radius_y).unwrap();
+        let z = ctx.div(z, radius_z).unwrap();
+        let length = nso_length(&[x, y, z], ctx);
+        let unit = ctx.sub(length, one).unwrap();
+        ctx.mul(unit, smallest).unwrap()
+      }
+      Self::HexPrismPrimitive {
+        radius,
+        height,
+        orientation,
+      } => {
+        let apothem = *radius * 3.0_f32.sqrt() / 2.0;
+        if apothem * 2.0 < settings.min_voxel_size
+          || *height < settings.min_voxel_size
+        {
+          return ctx.constant(1.0);
+        }
+
+        // the hexagon is the intersection of three slabs, one per pair of
+        // opposite edges
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        let mut planar = None;
+        for angle in orientation.edge_normal_angles() {
+          let (sin, cos) = angle.sin_cos();
+          let normal_x = ctx.constant(cos.into());
+          let normal_z = ctx.constant(sin.into());
+          let along_x = ctx.mul(x, normal_x).unwrap();
+          let along_z = ctx.mul(z, normal_z).unwrap();
+          let dot = ctx.add(along_x, along_z).unwrap();
+          let dot = ctx.abs(dot).unwrap();
+          planar = Some(match planar {
+            Some(planar) => ctx.max(planar, dot).unwrap(),
+            None => dot,
+          });
+        }
+        let apothem = ctx.constant(apothem.into());
+        let planar = ctx.sub(planar.unwrap(), apothem).unwrap();
+
+        let half_height = ctx.constant((*height / 2.0).into());
+        let abs_y = ctx.abs(y).unwrap();
+        let axial = ctx.sub(abs_y, half_height).unwrap();
+        nso_orthogonal_intersection(&[planar, axial], ctx)
+      }
+      Self::Extrude { profile, height } => {
+        if *height < settings.min_voxel_size {
+          return ctx.constant(1.0);
+        }
+
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        let planar = profile.compile(x, z, ctx, settings);
+        let half_height = ctx.constant((*height / 2.0).into());
+        let abs_y = ctx.abs(y).unwrap();
+        let axial = ctx.sub(abs_y, half_height).unwrap();
+        nso_orthogonal_intersection(&[planar, axial], ctx)
+      }
+      Self::Revolve { profile, axis } => {
+        let axes = [ctx.x(), ctx.y(), ctx.z()];
+        let (first, second) = axis.perpendicular();
+        let radial = nso_length(&[axes[first], axes[second]], ctx);
+        profile.compile(radial, axes[axis.index()], ctx, settings)
+      }
+      Self::ImportedMesh { grid } => {
+        let largest = grid.resolution.iter().max().copied().unwrap_or(0);
+        if largest as f32 * grid.cell_size < settings.min_voxel_size {
+          return ctx.constant(1.0);
+        }
+
+        grid.compile(ctx)
+      }
+      Self::Heightfield {
+        width,
+        depth,
+        samples,
+        scale,
+      } => {
+        if *width < settings.min_voxel_size || *depth < settings.min_voxel_size
+        {
+          return ctx.constant(1.0);
+        }
+
+        let half_width = ctx.constant((*width / 2.0).into());
+        let half_depth = ctx.constant((*depth / 2.0).into());
+        let neg_half_width = ctx.constant((-*width / 2.0).into());
+        let neg_half_depth = ctx.constant((-*depth / 2.0).into());
+        let x = ctx.x();
+        let y = ctx.y();
+        let z = ctx.z();
+        let clamped_x = ctx.max(x, neg_half_width).unwrap();
+        let clamped_x = ctx.min(clamped_x, half_width).unwrap();
+        let clamped_z = ctx.max(z, neg_half_depth).unwrap();
+        let clamped_z = ctx.min(clamped_z, half_depth).unwrap();
+
+        // the height changes by up to the steepest slope per unit across the
+        // footprint, so scale the vertical distance down to keep it a bound
+        let height = samples.compile(clamped_x, clamped_z, *width, *depth, ctx);
+        let scale_node = ctx.constant((*scale).into());
+        let height = ctx.mul(height, scale_node).unwrap();
+        let [slope_x, slope_z] = samples.max_slopes([
+          *width / (samples.columns() - 1) as f32,
+          *depth / (samples.rows() - 1) as f32,
+        ]);
+        let slope = scale.abs() * slope_x.hypot(slope_z);
+        let stretch = ctx.constant((1.0 + slope * slope).sqrt().into());
+        let vertical = ctx.sub(y, height).unwrap();
+        let vertical = ctx.div(vertical, stretch).unwrap();
+
+        let abs_x = ctx.abs(x).unwrap();
         let abs_z = ctx.abs(z).unwrap();
-        
-        let x = ctx.sub(abs_x, half_x).unwrap();
-        let y = ctx.sub(abs_y, half_y).unwrap();
-        let z = ctx.sub(abs_z, half_z).unwrap();
-        
-        let max_xy = ctx.max(x, y).unwrap();
-        ctx.max(max_xy, z).unwrap()
-      },
-      Self::CubePrimitive { size } => Self::RectPrismPrimitive { x: *size, y: *size, z: *size }.compile_solid(ctx, settings),
+        let side_x = ctx.sub(abs_x, half_width).unwrap();
+        let side_z = ctx.sub(abs_z, half_depth).unwrap();
+        nso_orthogonal_intersection(&[vertical, side_x, side_z], ctx)
+      }
     }
   }
   #[allow(clippy::match_single_binding)]
   fn compile_color(
     &self,
     ctx: &mut Context,
-    settings: &CompilationSettings,
+    _settings: &CompilationSettings,
+  ) -> [Node; 3] {
+    match self {
+      _ => nso_color([255, 255, 255], ctx),
+    }
+  }
+  fn compile_material(
+    &self,
+    ctx: &mut Context,
+    _settings: &CompilationSettings,
   ) -> Node {
+    ctx.constant(DEFAULT_MATERIAL_ID.into())
+  }
+}
+
+/// Computes the distance to each pair of faces of a box with half extents
+/// `half`, centered on the origin.
+fn box_slabs(half: [f32; 3], ctx: &mut Context) -> [Node; 3] {
+  let axes = [ctx.x(), ctx.y(), ctx.z()];
+  let mut slabs = axes;
+  for (i, axis) in axes.into_iter().enumerate() {
+    let half = ctx.constant(half[i].into());
+    let abs = ctx.abs(axis).unwrap();
+    slabs[i] = ctx.sub(abs, half).unwrap();
+  }
+  slabs
+}
+
+/// A coordinate axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Axis {
+  X,
+  Y,
+  Z,
+}
+
+impl Axis {
+  /// The index of the axis in an `[x, y, z]` array.
+  pub fn index(&self) -> usize {
     match self {
-      _ => {
-        let shape = self.compile_clamped_solid(ctx, settings);
-        let shape = nso_bleed(shape, 1.1, ctx);
+      Axis::X => 0,
+      Axis::Y => 1,
+      Axis::Z => 2,
+    }
+  }
 
-        nso_color(shape, [255, 255, 255], ctx)
-      }
+  /// The indices of the two axes perpendicular to this one, in xyz order.
+  pub fn perpendicular(&self) -> (usize, usize) {
+    match self {
+      Axis::X => (1, 2),
+      Axis::Y => (0, 2),
+      Axis::Z => (0, 1),
     }
   }
 }
 
 /// A shape operation. Shape operations are operations between 1 or 2 shapes.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ShapeOp {
   /// A unary operation. This takes modifies 1 shape, with the modification
   /// specified in the `UnaryOp` enum.
@@ -166,7 +612,7 @@ impl ShapeLike for ShapeOp {
     &self,
     ctx: &mut Context,
     settings: &CompilationSettings,
-  ) -> Node {
+  ) -> [Node; 3] {
     match self {
       ShapeOp::UnaryOp(unary_op, a) => {
         unary_op.compile_color(a.as_ref(), ctx, settings)
@@ -176,20 +622,122 @@ impl ShapeLike for ShapeOp {
       }
     }
   }
+  fn compile_material(
+    &self,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
+  ) -> Node {
+    match self {
+      ShapeOp::UnaryOp(unary_op, a) => {
+        unary_op.compile_material(a.as_ref(), ctx, settings)
+      }
+      ShapeOp::BinaryOp(binary_op, a, b) => {
+        binary_op.compile_material(a, b, ctx, settings)
+      }
+    }
+  }
 }
 
 /// A unary operation. This enum defines the possible unary operations and their
 /// parameters.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
   /// Translates a shape by a vector.
   Translate { pos: [f32; 3] },
   /// Scales a shape by a vector.
   Scale { scale: [f32; 3] },
-  /// Applies a matrix transform to a shape. This is currently not implemented.
+  /// Applies an affine matrix transform to a shape. The matrix is
+  /// column-major, as in `glam::Mat4::from_cols_array`. A singular matrix
+  /// is rejected by `Shape::check`, and collapses the shape to nothing if
+  /// compiled anyway.
   MatrixTransform { matrix: [f32; 16] },
+  /// Rotates a shape by Euler angles in radians, applied in XYZ order.
+  RotateEuler { angles: [f32; 3] },
+  /// Rotates a shape by `angle` radians around `axis`. A zero-length axis
+  /// leaves the shape unrotated.
+  RotateAxis { axis: [f32; 3], angle: f32 },
+  /// Rotates a shape by a quaternion, given as `[x, y, z, w]`. The quaternion
+  /// is normalized before use.
+  RotateQuat { quat: [f32; 4] },
+  /// Repeats a shape endlessly through space with spacing `period` on each
+  /// axis, as far as `NSO_REPEAT_EXTENT` from the origin. An axis with a
+  /// period of zero is not repeated. Parts of a shape further than 0.9
+  /// periods from its own copy are cut off.
+  Repeat { period: [f32; 3] },
+  /// Repeats a shape `count` times along each axis with spacing `period`,
+  /// centering the copies on the origin, cut off as with `Repeat`.
+  RepeatFinite { period: [f32; 3], count: [u32; 3] },
+  /// Mirrors a shape across the planes through `offset` perpendicular to
+  /// each enabled axis. The part of the shape on the positive side of each
+  /// plane is kept and reflected onto the negative side.
+  Mirror { axes: [bool; 3], offset: [f32; 3] },
+  /// Repeats a shape `count` times around `axis`, starting from the first
+  /// axis perpendicular to it in xyz order. Each copy is also mirrored across
+  /// its own center line, so the shape should be symmetric across that axis.
+  RadialSymmetry { axis: Axis, count: u32 },
+  /// Twists a shape around the y axis, rotating each slice by `rate` radians
+  /// per unit of height.
+  Twist { rate: f32 },
+  /// Bends a shape in the xy plane, curving the x axis towards y by `rate`
+  /// radians per unit of length.
+  Bend { rate: f32 },
+  /// Tapers a shape along the y axis, scaling each slice in x and z by
+  /// `1 + rate * y`.
+  Taper { rate: f32 },
+  /// Offsets the surface of a shape outwards by `distance`, rounding off its
+  /// edges. A negative distance shrinks it instead.
+  Offset { distance: f32 },
+  /// Hollows out a shape, leaving a wall of `thickness` just inside its
+  /// surface. The wall is never made thinner than the minimum voxel size, so
+  /// that it stays watertight when meshed.
+  Shell { thickness: f32 },
+  /// Elongates a shape by splitting it at the origin and pulling the halves
+  /// apart by `h` in each direction along each axis.
+  Elongate { h: [f32; 3] },
+  /// Displaces the surface of a shape by seeded fractal noise (see
+  /// `nso_noise`) with `frequency` lattice cells per unit, scaled by
+  /// `amplitude`.
+  Displace {
+    seed:      u32,
+    frequency: f32,
+    amplitude: f32,
+    octaves:   u32,
+  },
   /// Recolors a shape to a specific RGB color.
   Recolor { rgb: [u8; 3] },
+  /// Recolors a shape with a linear gradient along `axis`, from the first
+  /// color at `start` to the second at `end`.
+  LinearGradient {
+    colors: [[u8; 3]; 2],
+    axis:   Axis,
+    start:  f32,
+    end:    f32,
+  },
+  /// Recolors a shape with a radial gradient, from the first color at the
+  /// origin to the second at `radius`.
+  RadialGradient { colors: [[u8; 3]; 2], radius: f32 },
+  /// Recolors a shape with stripes of `width` across `axis`, alternating
+  /// between the two colors with the first starting at the origin.
+  Stripes {
+    colors: [[u8; 3]; 2],
+    axis:   Axis,
+    width:  f32,
+  },
+  /// Recolors a shape with a 3D checkerboard of cubes with edge `size`,
+  /// alternating between the two colors.
+  Checker { colors: [[u8; 3]; 2], size: f32 },
+  /// Tints the color of a shape towards `rgb` by seeded fractal noise (see
+  /// `nso_noise`), at most by `strength` between `0.0` and `1.0`.
+  NoiseTint {
+    rgb:       [u8; 3],
+    strength:  f32,
+    seed:      u32,
+    frequency: f32,
+    octaves:   u32,
+  },
+  /// Assigns a material to a shape, tagging its surface with `id`.
+  Material { id: u32, material: Material },
   /// Abbreviates a shape if it is smaller than a certain threshold. This is
   /// used to reduce voxel inaccuracies in the final model, by eliminating
   /// features smaller than the voxel size.
@@ -197,6 +745,37 @@ pub enum UnaryOp {
 }
 
 impl UnaryOp {
+  /// The rotation applied by a rotation operation, or the identity for any
+  /// other operation.
+  fn rotation(&self) -> glam::Quat {
+    match self {
+      UnaryOp::RotateEuler { angles } => {
+        let [x, y, z] = *angles;
+        glam::Quat::from_euler(glam::EulerRot::XYZ, x, y, z)
+      }
+      UnaryOp::RotateAxis { axis, angle } => {
+        match glam::Vec3::from_array(*axis).try_normalize() {
+          Some(axis) => glam::Quat::from_axis_angle(axis, *angle),
+          None => glam::Quat::IDENTITY,
+        }
+      }
+      UnaryOp::RotateQuat { quat } => glam::Vec4::from_array(*quat)
+        .try_normalize()
+        .map(glam::Quat::from_vec4)
+        .unwrap_or(glam::Quat::IDENTITY),
+      _ => glam::Quat::IDENTITY,
+    }
+  }
+
+  /// Whether the operation collapses any shape to nothing, leaving no
+  /// surface to carry attributes.
+  fn collapses(&self) -> bool {
+    match self {
+      UnaryOp::MatrixTransform { matrix } => invert_affine(*matrix).is_none(),
+      _ => false,
+    }
+  }
+
   fn compile_solid(
     &self,
     a: &Shape,
@@ -212,10 +791,78 @@ impl UnaryOp {
         let shape = a.compile_solid(ctx, settings);
         nso_scale(shape, *scale, ctx)
       }
-      UnaryOp::MatrixTransform { matrix: _ } => {
-        todo!();
+      UnaryOp::MatrixTransform { matrix } => {
+        let shape = a.compile_solid(ctx, settings);
+        match nso_matrix_transform(shape, *matrix, ctx) {
+          Some(shape) => shape,
+          None => ctx.constant(1.0),
+        }
+      }
+      UnaryOp::RotateEuler { .. }
+      | UnaryOp::RotateAxis { .. }
+      | UnaryOp::RotateQuat { .. } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_rotate(shape, self.rotation(), ctx)
+      }
+      UnaryOp::Repeat { period } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_repeat(shape, *period, ctx)
+      }
+      UnaryOp::RepeatFinite { period, count } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_repeat_finite(shape, *period, *count, ctx)
+      }
+      UnaryOp::Mirror { axes, offset } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_mirror(shape, *axes, *offset, ctx)
       }
-      UnaryOp::Recolor { .. } => a.compile_solid(ctx, settings),
+      UnaryOp::RadialSymmetry { axis, count } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_radial_symmetry(shape, *axis, *count, ctx)
+      }
+      UnaryOp::Twist { rate } => {
+        let shape = a.compile_solid(ctx, settings);
+        let shape = nso_twist(shape, *rate, ctx);
+        nso_twist_bound(shape, *rate, ctx)
+      }
+      UnaryOp::Bend { rate } => {
+        let shape = a.compile_solid(ctx, settings);
+        let shape = nso_bend(shape, *rate, ctx);
+        nso_bend_bound(shape, *rate, ctx)
+      }
+      UnaryOp::Taper { rate } => {
+        let shape = a.compile_solid(ctx, settings);
+        let shape = nso_taper(shape, *rate, ctx);
+        nso_taper_bound(shape, *rate, ctx)
+      }
+      UnaryOp::Offset { distance } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_offset(shape, *distance, ctx)
+      }
+      UnaryOp::Shell { thickness } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_shell(shape, thickness.max(settings.min_voxel_size), ctx)
+      }
+      UnaryOp::Elongate { h } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_elongate(shape, *h, ctx)
+      }
+      UnaryOp::Displace {
+        seed,
+        frequency,
+        amplitude,
+        octaves,
+      } => {
+        let shape = a.compile_solid(ctx, settings);
+        nso_displace(shape, *seed, *frequency, *amplitude, *octaves, ctx)
+      }
+      UnaryOp::Recolor { .. }
+      | UnaryOp::LinearGradient { .. }
+      | UnaryOp::RadialGradient { .. }
+      | UnaryOp::Stripes { .. }
+      | UnaryOp::Checker { .. }
+      | UnaryOp::NoiseTint { .. }
+      | UnaryOp::Material { .. } => a.compile_solid(ctx, settings),
       UnaryOp::Abbreviate { threshold } => {
         if settings.min_voxel_size < *threshold {
           a.compile_solid(ctx, settings)
@@ -231,14 +878,97 @@ impl UnaryOp {
     a: &Shape,
     ctx: &mut Context,
     settings: &CompilationSettings,
+  ) -> [Node; 3] {
+    match self {
+      UnaryOp::Recolor { rgb } => nso_color(*rgb, ctx),
+      UnaryOp::LinearGradient {
+        colors,
+        axis,
+        start,
+        end,
+      } => {
+        let t = nso_linear_gradient(*axis, *start, *end, ctx);
+        nso_color_ramp(*colors, t, ctx)
+      }
+      UnaryOp::RadialGradient { colors, radius } => {
+        let t = nso_radial_gradient(*radius, ctx);
+        nso_color_ramp(*colors, t, ctx)
+      }
+      UnaryOp::Stripes {
+        colors,
+        axis,
+        width,
+      } => {
+        let t = nso_stripes(*axis, *width, ctx);
+        nso_color_ramp(*colors, t, ctx)
+      }
+      UnaryOp::Checker { colors, size } => {
+        let t = nso_checker(*size, ctx);
+        nso_color_ramp(*colors, t, ctx)
+      }
+      UnaryOp::NoiseTint {
+        rgb,
+        strength,
+        seed,
+        frequency,
+        octaves,
+      } => {
+        let color = a.compile_color(ctx, settings);
+        let t = nso_noise_tint(*seed, *frequency, *octaves, *strength, ctx);
+        nso_mix_color(nso_color(*rgb, ctx), color, t, ctx)
+      }
+      _ if self.collapses() => nso_color([255, 255, 255], ctx),
+      _ => {
+        let color = a.compile_color(ctx, settings);
+        color.map(|color| self.transform_field(color, ctx))
+      }
+    }
+  }
+
+  fn compile_material(
+    &self,
+    a: &Shape,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
   ) -> Node {
     match self {
-      UnaryOp::Recolor { rgb } => {
-        let shape = a.compile_clamped_solid(ctx, settings);
-        let shape = nso_bleed(shape, 1.1, ctx);
-        nso_color(shape, *rgb, ctx)
+      UnaryOp::Material { id, .. } => ctx.constant((*id).into()),
+      _ if self.collapses() => ctx.constant(DEFAULT_MATERIAL_ID.into()),
+      _ => {
+        let material = a.compile_material(ctx, settings);
+        self.transform_field(material, ctx)
+      }
+    }
+  }
+
+  /// Moves a surface attribute field, like a color channel, along with the
+  /// space it was defined in. Operations that only change the surface itself
+  /// leave the field as it is.
+  fn transform_field(&self, field: Node, ctx: &mut Context) -> Node {
+    match self {
+      UnaryOp::Translate { pos } => nso_translate(field, *pos, ctx),
+      UnaryOp::Scale { scale } => nso_scale(field, *scale, ctx),
+      UnaryOp::MatrixTransform { matrix } => {
+        nso_matrix_transform(field, *matrix, ctx).unwrap()
+      }
+      UnaryOp::RotateEuler { .. }
+      | UnaryOp::RotateAxis { .. }
+      | UnaryOp::RotateQuat { .. } => nso_rotate(field, self.rotation(), ctx),
+      UnaryOp::Repeat { period } => nso_repeat(field, *period, ctx),
+      UnaryOp::RepeatFinite { period, count } => {
+        nso_repeat_finite(field, *period, *count, ctx)
+      }
+      UnaryOp::Mirror { axes, offset } => {
+        nso_mirror(field, *axes, *offset, ctx)
+      }
+      UnaryOp::RadialSymmetry { axis, count } => {
+        nso_radial_symmetry(field, *axis, *count, ctx)
       }
-      _ => a.compile_color(ctx, settings),
+      UnaryOp::Twist { rate } => nso_twist(field, *rate, ctx),
+      UnaryOp::Bend { rate } => nso_bend(field, *rate, ctx),
+      UnaryOp::Taper { rate } => nso_taper(field, *rate, ctx),
+      UnaryOp::Elongate { h } => nso_elongate(field, *h, ctx),
+      _ => field,
     }
   }
 }
@@ -246,6 +976,7 @@ impl UnaryOp {
 /// A binary operation. This enum defines the possible binary operations and
 /// their parameters.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOp {
   /// A union operation. This combines 2 shapes into 1.
   Union,
@@ -254,9 +985,18 @@ pub enum BinaryOp {
   /// An intersection operation. This takes the intersection of 2 shapes.
   Intersection,
   /// A replacement operation. This is a union operation where the properties
-  /// (only color currently) of the first shape are used where the shapes are
+  /// (color and material) of the first shape are used where the shapes are
   /// overlapping.
   Replacement,
+  /// A smooth union operation. This is a union where the seam between the
+  /// shapes is filleted with blend radius `k`.
+  SmoothUnion { k: f32 },
+  /// A smooth difference operation. This is a difference where the cut edge
+  /// is filleted with blend radius `k`.
+  SmoothDifference { k: f32 },
+  /// A smooth intersection operation. This is an intersection where the seam
+  /// is filleted with blend radius `k`.
+  SmoothIntersection { k: f32 },
 }
 
 impl BinaryOp {
@@ -288,6 +1028,21 @@ impl BinaryOp {
         let b = b.compile_solid(ctx, settings);
         nso_replacement(a, b, ctx)
       }
+      BinaryOp::SmoothUnion { k } => {
+        let a = a.compile_solid(ctx, settings);
+        let b = b.compile_solid(ctx, settings);
+        nso_smooth_union(a, b, *k, ctx)
+      }
+      BinaryOp::SmoothDifference { k } => {
+        let a = a.compile_solid(ctx, settings);
+        let b = b.compile_solid(ctx, settings);
+        nso_smooth_difference(a, b, *k, ctx)
+      }
+      BinaryOp::SmoothIntersection { k } => {
+        let a = a.compile_solid(ctx, settings);
+        let b = b.compile_solid(ctx, settings);
+        nso_smooth_intersection(a, b, *k, ctx)
+      }
     }
   }
 
@@ -308,32 +1063,324 @@ impl BinaryOp {
     b: &Shape,
     ctx: &mut Context,
     settings: &CompilationSettings,
-  ) -> Node {
-    match self {
-      BinaryOp::Union => {
-        // copy from replacement
-        BinaryOp::Replacement.compile_color(a, b, ctx, settings)
+  ) -> [Node; 3] {
+    let a_color = a.compile_color(ctx, settings);
+    // blend the colors proportionally across any fillet
+    match self.surface_weight(a, b, true, ctx, settings) {
+      Some(t) => {
+        let b_color = b.compile_color(ctx, settings);
+        nso_mix_color(a_color, b_color, t, ctx)
       }
-      BinaryOp::Difference => {
-        let shape = self.compile_clamped_solid(a, b, ctx, settings);
-        let color = a.compile_color(ctx, settings);
-        ctx.mul(color, shape).unwrap()
-      }
-      BinaryOp::Intersection => {
-        let solid = self.compile_solid(a, b, ctx, settings);
-        nso_color(solid, [255, 255, 255], ctx)
-      }
-      BinaryOp::Replacement => {
-        let (a_shape, b_shape) = (a, b);
-        let a = a_shape.compile_solid(ctx, settings);
-        let b = b_shape.compile_solid(ctx, settings);
-        let b = nso_difference(b, a, ctx);
-        let b = nso_clamp(b, ctx);
-        let a_color = a_shape.compile_color(ctx, settings);
-        let b_color = b_shape.compile_color(ctx, settings);
-        let b_color = ctx.mul(b_color, b).unwrap();
-        ctx.add(a_color, b_color).unwrap()
+      None => a_color,
+    }
+  }
+
+  pub fn compile_material(
+    &self,
+    a: &Shape,
+    b: &Shape,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
+  ) -> Node {
+    let a_material = a.compile_material(ctx, settings);
+    // ids can't be blended, so pick one outright, switching at the middle of
+    // any fillet
+    match self.surface_weight(a, b, false, ctx, settings) {
+      Some(t) => {
+        let b_material = b.compile_material(ctx, settings);
+        nso_mix(a_material, b_material, t, ctx)
       }
+      None => a_material,
+    }
+  }
+
+  /// Computes how much each point's surface attributes should come from `a`
+  /// rather than `b`, or `None` if they always come from `a`. If `blend` is
+  /// false, the weight is exactly `0` or `1`, switching at the middle of any
+  /// fillet.
+  fn surface_weight(
+    &self,
+    a: &Shape,
+    b: &Shape,
+    blend: bool,
+    ctx: &mut Context,
+    settings: &CompilationSettings,
+  ) -> Option<Node> {
+    let a = a.compile_solid(ctx, settings);
+    let b = b.compile_solid(ctx, settings);
+
+    // each point takes the attributes of whichever shape's surface is closest
+    // to it, or blends them across a fillet
+    let (nearer, further, k) = match self {
+      BinaryOp::Union => (a, b, 0.0),
+      BinaryOp::Replacement => (a, nso_difference(b, a, ctx), 0.0),
+      BinaryOp::Intersection => (b, a, 0.0),
+      BinaryOp::SmoothUnion { k } => (a, b, *k),
+      BinaryOp::SmoothIntersection { k } => (b, a, *k),
+      // the surface left by a difference (smooth or not) is either the first
+      // shape's own surface or its inside exposed by the cut
+      BinaryOp::Difference | BinaryOp::SmoothDifference { .. } => return None,
+    };
+    let t = if blend {
+      nso_smooth_weight(nearer, further, k, ctx)
+    } else {
+      nso_nearest_weight(nearer, further, ctx)
+    };
+    Some(t)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{builder::*, comp::Composition, import::Heightmap};
+
+  fn eval(shape: &Shape, point: [f64; 3]) -> f64 {
+    let mut ctx = Context::new();
+    let settings = CompilationSettings {
+      min_voxel_size: 0.01,
+    };
+    let node = shape.compile_solid(&mut ctx, &settings);
+    ctx.eval_xyz(node, point[0], point[1], point[2]).unwrap()
+  }
+
+  fn eval_color(shape: &Shape, point: [f64; 3]) -> [f64; 3] {
+    let mut ctx = Context::new();
+    let settings = CompilationSettings {
+      min_voxel_size: 0.01,
+    };
+    let nodes = shape.compile_color(&mut ctx, &settings);
+    nodes.map(|node| ctx.eval_xyz(node, point[0], point[1], point[2]).unwrap())
+  }
+
+  #[test]
+  fn test_binary_colors() {
+    let red = recolor(sphere(1.0), 255, 0, 0);
+    let blue = translate(recolor(sphere(1.0), 0, 0, 255), 1.5, 0.0, 0.0);
+
+    let shape = union(red.clone(), blue.clone());
+    assert_eq!(eval_color(&shape, [-1.0, 0.0, 0.0]), [1.0, 0.0, 0.0]);
+    assert_eq!(eval_color(&shape, [2.5, 0.0, 0.0]), [0.0, 0.0, 1.0]);
+
+    // where the two surfaces meet, the colors mix rather than adding up
+    let seam = eval_color(&shape, [0.75, 0.0, 0.0]);
+    assert!((seam[0] - 0.5).abs() < 1e-5 && (seam[2] - 0.5).abs() < 1e-5);
+
+    // an intersection takes the color of whichever surface bounds it
+    let shape = smooth_intersection(red.clone(), blue.clone(), 0.2);
+    assert_eq!(eval_color(&shape, [0.5, 0.0, 0.0]), [0.0, 0.0, 1.0]);
+    assert_eq!(eval_color(&shape, [1.0, 0.0, 0.0]), [1.0, 0.0, 0.0]);
+
+    let shape = replacement(red, blue);
+    assert_eq!(eval_color(&shape, [0.5, 0.0, 1.0]), [1.0, 0.0, 0.0]);
+    assert_eq!(eval_color(&shape, [1.5, 0.0, 1.0]), [0.0, 0.0, 1.0]);
+  }
+
+  #[test]
+  fn test_hard_binary_ops() {
+    let a = sphere(1.0);
+    let b = translate(sphere(1.0), 1.5, 0.0, 0.0);
+
+    // inside is negative, so a union keeps the smaller distance
+    let shape = union(a.clone(), b.clone());
+    assert!((eval(&shape, [0.9, 0.0, 0.0]) + 0.4).abs() < 1e-5);
+    assert!((eval(&shape, [-2.0, 0.0, 0.0]) - 1.0).abs() < 1e-5);
+
+    let shape = intersection(a.clone(), b.clone());
+    assert!((eval(&shape, [0.9, 0.0, 0.0]) + 0.1).abs() < 1e-5);
+    assert!((eval(&shape, [0.0, 0.0, 0.0]) - 0.5).abs() < 1e-5);
+
+    // inside `a` a replacement keeps its distance, elsewhere it is a union
+    let shape = replacement(a, b);
+    assert!((eval(&shape, [0.9, 0.0, 0.0]) + 0.1).abs() < 1e-5);
+    assert!((eval(&shape, [2.0, 0.0, 0.0]) + 0.5).abs() < 1e-5);
+    assert!((eval(&shape, [3.5, 0.0, 0.0]) - 1.0).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_sphere_distance() {
+    // the distance is exact, not squared
+    assert!((eval(&sphere(1.0), [3.0, 0.0, 0.0]) - 2.0).abs() < 1e-5);
+    assert!((eval(&sphere(2.0), [0.0, 0.0, 0.0]) + 2.0).abs() < 1e-5);
+    assert!((eval(&sphere(1.0), [0.0, 2.0, 2.0]) - 1.828427).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_singular_matrix_is_rejected() {
+    let flat = [
+      1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0,
+      0.0, 1.0,
+    ];
+    assert!(matrix_transform(sphere(1.0), flat).is_err());
+    let moved = glam::Mat4::from_translation(glam::Vec3::X).to_cols_array();
+    let moved = matrix_transform(sphere(1.0), moved).unwrap();
+    assert!(moved.check().is_ok());
+
+    // a singular transform loaded from elsewhere fails to compile for meshing
+    let shape = Shape::ShapeOp(ShapeOp::UnaryOp(
+      UnaryOp::MatrixTransform { matrix: flat },
+      Box::new(sphere(1.0)),
+    ));
+    let shape = union(moved, shape);
+    assert!(shape.check().is_err());
+    let comp = Composition::from(vec![(shape, [0.0; 3])]);
+    let settings = CompilationSettings {
+      min_voxel_size: 0.01,
+    };
+    let tapes = comp.compile_tapes::<fidget::vm::Eval>(
+      &settings,
+      crate::mesh::ColorMode::Disabled,
+      |node, _| node,
+    );
+    assert!(tapes.is_err());
+  }
+
+  #[test]
+  fn test_binary_materials() {
+    let eval_material = |shape: &Shape, point: [f64; 3]| {
+      let mut ctx = Context::new();
+      let settings = CompilationSettings {
+        min_voxel_size: 0.01,
+      };
+      let node = shape.compile_material(&mut ctx, &settings);
+      ctx.eval_xyz(node, point[0], point[1], point[2]).unwrap()
+    };
+    let metal = material(sphere(1.0), 3, 0.2, 1.0, [0.0; 3]);
+    let plain = translate(sphere(1.0), 1.5, 0.0, 0.0);
+
+    // even across a wide fillet, the id switches instead of blending
+    let shape = smooth_union(metal.clone(), plain.clone(), 1.0);
+    assert_eq!(eval_material(&shape, [0.5, 0.0, 0.0]), 3.0);
+    assert_eq!(eval_material(&shape, [1.0, 0.0, 0.0]), 0.0);
+
+    let mut table = MaterialTable::new();
+    shape.collect_materials(&mut table);
+    assert_eq!(table.len(), 1);
+    assert_eq!(table[&3].metallic, 1.0);
+
+    // exactly on the seam the id is one of the two, never one between them
+    let stone = material(plain, 5, 0.9, 0.0, [0.0; 3]);
+    let metal = material(sphere(1.0), 1, 0.2, 1.0, [0.0; 3]);
+    let shape = union(metal.clone(), stone.clone());
+    assert_eq!(eval_material(&shape, [0.75, 0.0, 0.0]), 1.0);
+    assert_eq!(eval_material(&shape, [0.7501, 0.0, 0.0]), 5.0);
+    let shape = smooth_union(metal.clone(), stone.clone(), 1.0);
+    assert_eq!(eval_material(&shape, [0.75, 0.5, 0.0]), 1.0);
+
+    let comp = Composition::from(vec![(metal, [0.0; 3]), (stone, [0.0; 3])]);
+    let mut ctx = Context::new();
+    let settings = CompilationSettings {
+      min_voxel_size: 0.01,
+    };
+    let node = comp.compile_material(&mut ctx, &settings);
+    assert_eq!(ctx.eval_xyz(node, 0.75, 0.0, 0.0).unwrap(), 1.0);
+    assert_eq!(ctx.eval_xyz(node, 0.7501, 0.0, 0.0).unwrap(), 5.0);
+  }
+
+  #[test]
+  fn test_primitive_distances() {
+    // past the rim of a cylinder the distance is to the edge, not the caps
+    let d = eval(&cylinder(1.0, 2.0), [4.0, 5.0, 0.0]);
+    assert!((d - 5.0).abs() < 1e-5);
+    // a capsule is a sphere swept along its straight section
+    let d = eval(&capsule(0.5, 2.0), [0.0, 3.0, 0.0]);
+    assert!((d - 1.5).abs() < 1e-5);
+    let d = eval(&torus(2.0, 0.5), [2.0, 0.0, 0.0]);
+    assert!((d + 0.5).abs() < 1e-5);
+    let d = eval(&plane(0.0, 2.0, 0.0, 1.0), [3.0, 4.0, 3.0]);
+    assert!((d - 3.0).abs() < 1e-5);
+    // a pointy hexagon reaches its full radius along z
+    let pointy = hex_prism(1.0, 1.0, HexOrientation::Pointy);
+    assert!(eval(&pointy, [0.0, 0.0, 0.99]) < 0.0);
+    let flat = hex_prism(1.0, 1.0, HexOrientation::Flat);
+    assert!(eval(&flat, [0.0, 0.0, 0.99]) > 0.0);
+  }
+
+  #[test]
+  fn test_box_distances() {
+    // off a corner the exact box measures to the corner itself
+    let d = eval(&box_(2.0, 2.0, 2.0), [4.0, 5.0, 1.0]);
+    assert!((d - 5.0).abs() < 1e-5);
+    let d = eval(&cheap_box(2.0, 2.0, 2.0), [4.0, 5.0, 1.0]);
+    assert!((d - 4.0).abs() < 1e-5);
+    let d = eval(&rounded_box(2.0, 2.0, 2.0, 0.5), [2.0, 0.0, 0.0]);
+    assert!((d - 1.0).abs() < 1e-5);
+    let d = eval(&rounded_box(2.0, 2.0, 2.0, 0.5), [1.0, 1.0, 0.0]);
+    assert!((d - (0.5 * 2.0_f64.sqrt() - 0.5)).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_offset_shell_elongate() {
+    let d = eval(&offset(sphere(1.0), 0.5), [2.0, 0.0, 0.0]);
+    assert!((d - 0.5).abs() < 1e-5);
+    // the wall sits just inside the original surface
+    let pot = shell(sphere(1.0), 0.2);
+    assert!(eval(&pot, [0.9, 0.0, 0.0]) < 0.0);
+    assert!(eval(&pot, [0.7, 0.0, 0.0]) > 0.0);
+    let d = eval(&elongate(sphere(1.0), 2.0, 0.0, 0.0), [2.5, 0.0, 0.0]);
+    assert!((d + 0.5).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_extrude_and_revolve() {
+    // an L shape, which has a reflex corner at (1, 1)
+    let l_shape = polygon(vec![
+      [0.0, 0.0],
+      [2.0, 0.0],
+      [2.0, 1.0],
+      [1.0, 1.0],
+      [1.0, 2.0],
+      [0.0, 2.0],
+    ]);
+    let shape = extrude(l_shape, 2.0);
+    assert!((eval(&shape, [0.5, 0.0, 0.5]) + 0.5).abs() < 1e-5);
+    assert!((eval(&shape, [1.5, 0.0, 0.5]) + 0.5).abs() < 1e-5);
+    assert!((eval(&shape, [1.5, 0.0, 1.5]) - 0.5).abs() < 1e-5);
+    assert!((eval(&shape, [0.9, 0.0, 0.9]) + 0.02_f64.sqrt()).abs() < 1e-5);
+    assert!((eval(&shape, [3.0, 0.0, 0.5]) - 1.0).abs() < 1e-5);
+    assert!((eval(&shape, [0.5, 2.0, 0.5]) - 1.0).abs() < 1e-5);
+
+    // points along an edge and a spike doubling back leave the shape as is
+    let cluttered = polygon(vec![
+      [0.0, 0.0],
+      [1.0, 0.0],
+      [2.0, 0.0],
+      [2.0, 1.0],
+      [1.0, 1.0],
+      [1.0, 2.0],
+      [0.5, 2.0],
+      [0.5, 2.5],
+      [0.5, 2.0],
+      [0.0, 2.0],
+      [0.0, 1.0],
+    ]);
+    let cluttered = extrude(cluttered, 2.0);
+    for point in [[0.9, 0.0, 0.9], [1.5, 0.0, 1.5], [0.5, 0.0, 2.3]] {
+      assert!((eval(&cluttered, point) - eval(&shape, point)).abs() < 1e-5);
     }
+
+    // a circle away from the axis revolves into a torus
+    let shape = revolve(translate_profile(circle(0.5), 2.0, 0.0), Axis::Y);
+    assert!((eval(&shape, [0.0, 0.0, 2.0]) + 0.5).abs() < 1e-5);
+    assert!((eval(&shape, [0.0, 0.0, 0.0]) - 1.5).abs() < 1e-5);
+    assert!((eval(&shape, [2.0, 1.0, 0.0]) - 0.5).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_heightfield() {
+    // a ramp rising by 1 across x
+    let samples = Heightmap::new(2, 2, vec![0.0, 1.0, 0.0, 1.0]).unwrap();
+    let shape = heightfield(2.0, 2.0, samples, 2.0);
+    let stretch = 2.0_f64.sqrt();
+    assert!(eval(&shape, [0.0, 1.0, 0.0]).abs() < 1e-5);
+    assert!((eval(&shape, [0.0, 0.5, 0.0]) + 0.5 / stretch).abs() < 1e-5);
+    assert!((eval(&shape, [-1.0, 1.0, 0.0]) - 1.0 / stretch).abs() < 1e-5);
+    assert!((eval(&shape, [0.0, -1.0, 2.0]) - 1.0).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_small_primitives_are_abbreviated() {
+    let d = eval(&cylinder(0.001, 2.0), [0.0, 0.0, 0.0]);
+    assert_eq!(d, 1.0);
   }
 }
@@ -0,0 +1,133 @@
+use std::{
+  path::{Path, PathBuf},
+  time::Instant,
+};
+
+use anyhow::{Error, Result};
+use clap::Parser;
+use planiscope::{
+  comp::Composition,
+  export::{save_mesh, MeshFormat},
+  lod::{lod_depths, mesh_lods},
+  mesh::{CancelToken, ColorMode, MeshSettings, NormalsMode},
+  pls::PlsAsset,
+  rhai::eval_with_assets,
+};
+
+/// Meshes a planiscope shape without the editor, writing the result as a
+/// `.pls` asset with the mesh baked in, or exporting it as a mesh file.
+#[derive(Parser)]
+#[command(name = "planiscope", version)]
+struct Args {
+  /// A rhai script, or a `.pls` asset to re-bake.
+  input:          PathBuf,
+  /// Where to write the `.pls` asset, or an `.obj`, `.stl`, `.ply`, `.gltf`
+  /// or `.glb` file to export the mesh alone.
+  #[arg(short, long)]
+  output:         PathBuf,
+  /// The center of the meshed region.
+  #[arg(long, num_args = 3, default_values_t = [0.0, 0.0, 0.0])]
+  translate:      Vec<f32>,
+  /// How far the meshed region reaches from its center along each axis.
+  #[arg(long, num_args = 3, default_values_t = [5.0, 5.0, 5.0])]
+  scale:          Vec<f32>,
+  /// The octree depth of the smallest voxels.
+  #[arg(long, default_value_t = 6)]
+  finest_depth:   u8,
+  /// The octree depth of the largest voxels.
+  #[arg(long, default_value_t = 0)]
+  coarsest_depth: u8,
+  /// How many levels of detail to bake, each one octree level shallower than
+  /// the last.
+  #[arg(long, default_value_t = 1)]
+  lods:           usize,
+  /// The number of threads to mesh with. Defaults to one per core.
+  #[arg(long)]
+  threads:        Option<u8>,
+  /// Skip evaluating vertex colors.
+  #[arg(long)]
+  no_colors:      bool,
+  /// Use flat normals instead of evaluating smooth ones from the surface.
+  #[arg(long)]
+  flat_normals:   bool,
+}
+
+fn main() -> Result<()> {
+  let args = Args::parse();
+  let translate: [f32; 3] = args.translate.as_slice().try_into()?;
+  let scale: [f32; 3] = args.scale.as_slice().try_into()?;
+
+  let start = Instant::now();
+  let (script, composition) = read_input(&args.input)?;
+  println!("read {} in {:?}", args.input.display(), start.elapsed());
+
+  let start = Instant::now();
+  let mut mesh_settings =
+    MeshSettings::default().coarsest_depth(args.coarsest_depth);
+  if let Some(threads) = args.threads {
+    mesh_settings = mesh_settings.threads(threads);
+  }
+  if args.no_colors {
+    mesh_settings = mesh_settings.colors(ColorMode::Disabled);
+  }
+  if args.flat_normals {
+    mesh_settings = mesh_settings.normals(NormalsMode::Flat);
+  }
+  let depths = lod_depths(args.finest_depth, args.coarsest_depth, args.lods);
+  if depths.is_empty() {
+    return Err(Error::msg(
+      "nothing to mesh: --lods must be at least 1, and --coarsest-depth at \
+       most --finest-depth",
+    ));
+  }
+  let lods = mesh_lods::<fidget::vm::Eval>(
+    &composition,
+    translate,
+    scale,
+    &depths,
+    &mesh_settings,
+    &CancelToken::new(),
+    |_, _| {},
+  )?;
+  println!("meshed in {:?}", start.elapsed());
+  for lod in &lods {
+    println!(
+      "depth {} mesh has {} vertices and {} triangles",
+      lod.max_depth,
+      lod.mesh.vertices.len(),
+      lod.mesh.triangles.len()
+    );
+  }
+
+  let start = Instant::now();
+  if MeshFormat::from_path(&args.output).is_some() {
+    // mesh formats hold a single mesh, so only the most detailed level
+    save_mesh(&lods[0].mesh, &args.output)?;
+    println!("wrote {} in {:?}", args.output.display(), start.elapsed());
+    return Ok(());
+  }
+  let mut asset = PlsAsset::new(composition);
+  asset.script = script;
+  asset.lods = lods;
+  asset.save(&args.output)?;
+  println!("wrote {} in {:?}", args.output.display(), start.elapsed());
+
+  Ok(())
+}
+
+/// Reads the script (if there is one) and the composition from a rhai script
+/// or a `.pls` asset. A script can import files from its own directory.
+fn read_input(path: &Path) -> Result<(Option<String>, Composition)> {
+  if path.extension().is_some_and(|extension| extension == "pls") {
+    let asset = PlsAsset::load(path)?;
+    return Ok((asset.script, asset.composition));
+  }
+
+  let script = std::fs::read_to_string(path)?;
+  let asset_root = match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new("."),
+  };
+  let composition = Composition::from(eval_with_assets(&script, asset_root)?);
+  Ok((Some(script), composition))
+}
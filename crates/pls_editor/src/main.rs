@@ -1,4 +1,8 @@
-use std::f32::consts::{FRAC_PI_4, PI};
+use std::{
+  f32::consts::{FRAC_PI_4, PI},
+  path::Path,
+  sync::{Arc, Mutex},
+};
 
 use anyhow::{Error, Result};
 use bevy::{
@@ -8,9 +12,12 @@ use bevy::{
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use futures_lite::future;
 use planiscope::{
-  comp::{CompilationSettings, Composition},
-  mesh::FullMesh,
-  rhai::eval,
+  comp::Composition,
+  lod::{lod_depths, mesh_lods, select_lods, LodGroup, LodLevel},
+  material::Material,
+  mesh::{CancelToken, ColorMode, MeshProgress, MeshSettings, NormalsMode},
+  pls::{PlsAsset, PlsLod},
+  rhai::eval_with_assets,
   shape::Shape,
 };
 
@@ -19,15 +26,17 @@ fn main() {
     .add_plugins(DefaultPlugins)
     .add_plugins(EguiPlugin)
     // .add_plugins(bevy_panorbit_camera::PanOrbitCameraPlugin)
-    .init_resource::<ModelMaterialHandle>()
     .init_resource::<UiSettings>()
     .init_resource::<UiCode>()
+    .init_resource::<BakedMesh>()
+    .init_resource::<FileStatus>()
     .add_systems(Startup, configure_visuals_system)
     .add_systems(Startup, configure_ui_state_system)
     .add_systems(Startup, setup_3d_env)
     .add_systems(Update, ui_system)
     .add_systems(Update, spawn_compute_mesh_jobs)
     .add_systems(Update, handle_tasks)
+    .add_systems(Update, select_lods)
     .add_systems(Update, animate_light_direction)
     .add_systems(Update, draw_gizmos)
     .run();
@@ -63,23 +72,53 @@ impl Default for UiSettings {
 #[derive(Default, Resource)]
 struct UiCode(pub String);
 
+/// The meshes of the current model, from the most to the least detailed, kept
+/// so that they can be saved.
+#[derive(Default, Resource)]
+struct BakedMesh(Vec<PlsLod>);
+
+/// The outcome of the last save or load.
+#[derive(Default, Resource)]
+struct FileStatus(Option<String>);
+
+/// How many levels of detail the model is meshed at.
+const LOD_COUNT: usize = 3;
+
+/// The directory scripts can import files from with `import_obj`: the one
+/// the editor was started in.
+const ASSET_ROOT: &str = ".";
+
+/// Evaluates a script from the editor, with access to the asset directory.
+fn eval(code: &str) -> Result<Vec<(Shape, [f32; 3])>> {
+  eval_with_assets(code, Path::new(ASSET_ROOT))
+}
+
+/// Each level of detail of a model, with its Bevy meshes split by material.
+type ModelLods = Vec<(PlsLod, Vec<(Material, Mesh)>)>;
+
+/// The level of detail being meshed and its progress.
+type JobProgress = Arc<Mutex<Option<(usize, MeshProgress)>>>;
+
 #[derive(Component)]
-struct ComputeMeshJob(Task<Result<Mesh>>);
+struct ComputeMeshJob {
+  task:     Task<Result<ModelLods>>,
+  /// Stops the build when the job is replaced by a newer one.
+  cancel:   CancelToken,
+  /// The latest progress reported by the build.
+  progress: JobProgress,
+}
 
 #[derive(Component)]
 struct CurrentModel;
 
-#[derive(Resource, Deref)]
-struct ModelMaterialHandle(Handle<StandardMaterial>);
-
-impl FromWorld for ModelMaterialHandle {
-  fn from_world(world: &mut World) -> Self {
-    let mut materials = world
-      .get_resource_mut::<Assets<StandardMaterial>>()
-      .unwrap();
-    ModelMaterialHandle(
-      materials.add(StandardMaterial::from(Color::rgb(1.0, 1.0, 1.0))),
-    )
+fn standard_material(material: &Material) -> StandardMaterial {
+  let [r, g, b] = material.emissive;
+  StandardMaterial {
+    base_color: Color::rgb(1.0, 1.0, 1.0),
+    perceptual_roughness: material.roughness,
+    metallic: material.metallic,
+    emissive: Color::rgb_linear(r, g, b),
+    ..default()
   }
 }
 
@@ -107,6 +146,9 @@ fn ui_system(
   mut contexts: EguiContexts,
   mut ui_settings: ResMut<UiSettings>,
   mut ui_code: ResMut<UiCode>,
+  baked_mesh: Res<BakedMesh>,
+  mut file_status: ResMut<FileStatus>,
+  jobs: Query<&ComputeMeshJob>,
 ) {
   let ctx = contexts.ctx_mut();
 
@@ -122,12 +164,43 @@ fn ui_system(
         ui.label(".pls");
       });
 
+      ui.horizontal(|ui| {
+        let path = format!("{}.pls", ui_settings.name);
+        if ui.button("Save").clicked() {
+          let saved = save_pls(&path, &ui_code.0, &baked_mesh.0);
+          file_status.0 = Some(match saved {
+            Ok(()) => format!("saved {}", path),
+            Err(error) => error.to_string(),
+          });
+        }
+        if ui.button("Load").clicked() {
+          file_status.0 = Some(match load_pls(&path) {
+            Ok(script) => {
+              ui_code.0 = script;
+              format!("loaded {}", path)
+            }
+            Err(error) => error.to_string(),
+          });
+        }
+        ui.label(file_status.0.clone().unwrap_or_default());
+      });
+
       ui.vertical(|ui| {
         ui.label("Shape Code: ");
         ui.code_editor(&mut ui_code.0);
       });
 
       ui.label(ui_settings.parsing_error.clone().unwrap_or("".to_string()));
+      if let Some((level, progress)) =
+        jobs.iter().find_map(|job| *job.progress.lock().unwrap())
+      {
+        ui.label(format!(
+          "Meshing LOD {}: {:?} ({:.0}%)",
+          level,
+          progress.phase,
+          progress.fraction * 100.0
+        ));
+      }
       
       ui.separator();
 
@@ -262,60 +335,71 @@ fn draw_gizmos(mut gizmos: Gizmos) {
 fn compute_mesh(
   settings: UiSettings,
   shapes: Vec<(Shape, [f32; 3])>,
-) -> Result<Mesh> {
-  let mut composition = Composition::new();
-  shapes.into_iter().for_each(|(shape, pos)| {
-    composition.add_shape(shape, pos);
-  });
-
-  let smallest_scale_dim = settings
-    .scale
-    .iter()
-    .min_by(|a, b| a.total_cmp(b))
-    .ok_or(Error::msg("unable to find smallest scale axis"))?;
-  let min_voxel_size =
-    smallest_scale_dim * 2.0 / 2.0f32.powi(settings.max_depth as i32);
-
-  let mut ctx = fidget::Context::new();
-  let comp_settings = CompilationSettings { min_voxel_size };
-
-  let solid_root_node = composition.compile_solid(&mut ctx, &comp_settings);
-  let color_root_node = composition.compile_color(&mut ctx, &comp_settings);
-
-  let solid_root_node = planiscope::nso::nso_normalize_region(
-    solid_root_node,
-    settings.translate,
-    settings.scale,
-    &mut ctx,
-  );
-  let color_root_node = planiscope::nso::nso_normalize_region(
-    color_root_node,
-    settings.translate,
-    settings.scale,
-    &mut ctx,
-  );
-
-  let solid_tape: fidget::eval::Tape<fidget::vm::Eval> =
-    ctx.get_tape(solid_root_node).unwrap();
-  let color_tape: fidget::eval::Tape<fidget::vm::Eval> =
-    ctx.get_tape(color_root_node).unwrap();
-
-  let mut full_mesh = FullMesh::mesh_new(
-    &solid_tape,
-    if settings.use_colors {
-      Some(&color_tape)
+  cancel: CancelToken,
+  progress: JobProgress,
+) -> Result<ModelLods> {
+  let composition = Composition::from(shapes);
+
+  let mesh_settings = MeshSettings::default()
+    .coarsest_depth(settings.min_depth.try_into()?)
+    .normals(if settings.smooth_normals {
+      NormalsMode::Smooth
     } else {
-      None
-    },
-    settings.smooth_normals,
+      NormalsMode::Flat
+    })
+    .colors(if settings.use_colors {
+      ColorMode::Vertex
+    } else {
+      ColorMode::Disabled
+    });
+  let depths = lod_depths(
     settings.max_depth.try_into()?,
     settings.min_depth.try_into()?,
+    LOD_COUNT,
   );
+  let lods = mesh_lods::<fidget::vm::Eval>(
+    &composition,
+    settings.translate,
+    settings.scale,
+    &depths,
+    &mesh_settings,
+    &cancel,
+    |level, latest| *progress.lock().unwrap() = Some((level, latest)),
+  )?;
+
+  let materials = composition.materials();
+  Ok(
+    lods
+      .into_iter()
+      .map(|lod| {
+        let meshes: Vec<(u32, Mesh)> = lod.mesh.clone().into();
+        let meshes = meshes
+          .into_iter()
+          .map(|(id, mesh)| {
+            (materials.get(&id).copied().unwrap_or_default(), mesh)
+          })
+          .collect();
+        (lod, meshes)
+      })
+      .collect(),
+  )
+}
 
-  full_mesh.prune();
-  full_mesh.denormalize(settings.translate.into(), settings.scale.into());
+/// Saves the script, the composition it evaluates to and the current meshes
+/// to a `.pls` file.
+fn save_pls(path: &str, script: &str, baked_mesh: &[PlsLod]) -> Result<()> {
+  let composition = Composition::from(eval(script)?);
+  let mut asset = PlsAsset::new(composition);
+  asset.script = Some(script.to_string());
+  asset.lods = baked_mesh.to_vec();
+  asset.save(path)
+}
 
-  Ok(full_mesh.into())
+/// Loads the script from a `.pls` file.
+fn load_pls(path: &str) -> Result<String> {
+  PlsAsset::load(path)?
+    .script
+    .ok_or(Error::msg(format!("{} has no script to edit", path)))
 }
 
 fn spawn_compute_mesh_jobs(
@@ -324,24 +408,45 @@ fn spawn_compute_mesh_jobs(
   mut previous_settings: Local<UiSettings>,
   ui_code: Res<UiCode>,
   mut previous_code: Local<String>,
-  previous_jobs: Query<Entity, With<ComputeMeshJob>>,
+  previous_jobs: Query<(Entity, &ComputeMeshJob)>,
+  mut baked_mesh: ResMut<BakedMesh>,
 ) {
   let pool = AsyncComputeTaskPool::get();
 
   if ui_code.0 != *previous_code || *previous_settings != *settings {
     let shape_code = ui_code.0.clone();
 
-    for job in previous_jobs.iter() {
-      commands.entity(job).despawn_recursive();
+    // dropping a task doesn't stop a build that's already running, so cancel
+    // it too. It still finishes any octree phase it's in, but skips the rest
+    // of its phases and levels of detail.
+    for (entity, job) in previous_jobs.iter() {
+      job.cancel.cancel();
+      commands.entity(entity).despawn_recursive();
     }
+    baked_mesh.0.clear();
 
     match eval(&shape_code) {
+      // an empty chain of levels would replace the model with nothing
+      Ok(_) if settings.min_depth > settings.max_depth => {
+        settings.parsing_error =
+          Some("the min depth must be at most the max depth".to_string());
+      }
       Ok(shapes) => {
         settings.parsing_error = None;
         let ui_settings = settings.clone();
-        let task = pool.spawn(async move { compute_mesh(ui_settings, shapes) });
-
-        commands.spawn(ComputeMeshJob(task));
+        let cancel = CancelToken::new();
+        let progress = Arc::new(Mutex::new(None));
+        let task = pool.spawn({
+          let cancel = cancel.clone();
+          let progress = progress.clone();
+          async move { compute_mesh(ui_settings, shapes, cancel, progress) }
+        });
+
+        commands.spawn(ComputeMeshJob {
+          task,
+          cancel,
+          progress,
+        });
       }
       Err(error) => {
         settings.parsing_error = Some(error.to_string());
@@ -358,25 +463,59 @@ fn handle_tasks(
   mut compute_mesh_jobs: Query<(Entity, &mut ComputeMeshJob)>,
   current_model: Query<Entity, With<CurrentModel>>,
   mut meshes: ResMut<Assets<Mesh>>,
-  material: Res<ModelMaterialHandle>,
+  mut materials: ResMut<Assets<StandardMaterial>>,
+  mut baked_mesh: ResMut<BakedMesh>,
+  settings: Res<UiSettings>,
 ) {
-  for (entity, mut task) in &mut compute_mesh_jobs {
-    if let Some(Ok(mesh)) = future::block_on(future::poll_once(&mut task.0)) {
-      // Despawn the previous model
-      for old_model in current_model.iter() {
-        commands.entity(old_model).despawn_recursive();
+  for (entity, mut job) in &mut compute_mesh_jobs {
+    let Some(result) = future::block_on(future::poll_once(&mut job.task))
+    else {
+      continue;
+    };
+    commands.entity(entity).despawn_recursive();
+
+    let lods = match result {
+      Ok(lods) => lods,
+      Err(error) => {
+        warn!("failed to build mesh: {}", error);
+        continue;
       }
+    };
 
-      commands.entity(entity).despawn_recursive();
+    // Despawn the previous model
+    for old_model in current_model.iter() {
+      commands.entity(old_model).despawn_recursive();
+    }
 
-      commands.spawn((
-        PbrBundle {
-          mesh: meshes.add(mesh),
-          material: material.clone(),
-          ..default()
-        },
-        CurrentModel,
-      ));
+    // each level is shown up to twice as far as the one before it, starting
+    // at twice the size of the meshed region
+    let region_size = settings.scale.into_iter().fold(0.0, f32::max) * 2.0;
+    let distances = (0..lods.len())
+      .map(|level| region_size * 2.0f32.powi(level as i32 + 1))
+      .collect();
+
+    let mut model = commands.spawn((
+      SpatialBundle::default(),
+      LodGroup { distances },
+      CurrentModel,
+    ));
+    baked_mesh.0.clear();
+    for (level, (lod, submeshes)) in lods.into_iter().enumerate() {
+      baked_mesh.0.push(lod);
+
+      // spawn one entity per material, since each needs its own handle
+      model.with_children(|parent| {
+        for (material, mesh) in submeshes {
+          parent.spawn((
+            PbrBundle {
+              mesh: meshes.add(mesh),
+              material: materials.add(standard_material(&material)),
+              ..default()
+            },
+            LodLevel(level),
+          ));
+        }
+      });
     }
   }
 }
@@ -7,7 +7,7 @@ use bevy_pixel_cam::PixelCamBundle;
 use planiscope::{
   builder::*,
   comp::{CompilationSettings, Composition},
-  mesh::FullMesh,
+  mesh::{CancelToken, FullMesh, MeshSettings},
 };
 use timing::start;
 
@@ -57,7 +57,7 @@ fn setup(
   };
   let solid_root_node =
     composition.compile_solid(&mut ctx, &compilation_settings);
-  let color_root_node =
+  let color_root_nodes =
     composition.compile_color(&mut ctx, &compilation_settings);
 
   let solid_root_node = planiscope::nso::nso_normalize_region(
@@ -66,23 +66,33 @@ fn setup(
     [5.0, 5.0, 5.0],
     &mut ctx,
   );
-  let color_root_node = planiscope::nso::nso_normalize_region(
-    color_root_node,
-    [0.0, 0.0, 0.0],
-    [5.0, 5.0, 5.0],
-    &mut ctx,
-  );
+  let color_root_nodes = color_root_nodes.map(|color_root_node| {
+    planiscope::nso::nso_normalize_region(
+      color_root_node,
+      [0.0, 0.0, 0.0],
+      [5.0, 5.0, 5.0],
+      &mut ctx,
+    )
+  });
 
   let solid_tape: fidget::eval::Tape<fidget::vm::Eval> =
     ctx.get_tape(solid_root_node).unwrap();
-  let color_tape: fidget::eval::Tape<fidget::vm::Eval> =
-    ctx.get_tape(color_root_node).unwrap();
+  let color_tapes: [fidget::eval::Tape<fidget::vm::Eval>; 3] = color_root_nodes
+    .map(|color_root_node| ctx.get_tape(color_root_node).unwrap());
 
   println!("building mesh...");
   let start = start();
-  let mut full_mesh: FullMesh = FullMesh::mesh_new(&solid_tape, &color_tape, 7);
+  let mesh_settings = MeshSettings::default().finest_depth(7).coarsest_depth(3);
+  let mut full_mesh: FullMesh = FullMesh::mesh_new(
+    &solid_tape,
+    Some(&color_tapes),
+    None,
+    &mesh_settings,
+    &CancelToken::new(),
+    |_| {},
+  )
+  .unwrap();
   println!("mesh has {} vertices", full_mesh.vertices.len());
-  full_mesh.prune();
   full_mesh.denormalize([0.0, 0.0, 0.0].into(), [5.0, 5.0, 5.0].into());
   let mesh = Mesh::from(full_mesh);
   println!("built mesh in {} ms", start.elapsed().as_millis());